@@ -0,0 +1,182 @@
+// Persistent settings of the application, stored as key file in the XDG config directory
+// (usually ~/.config/samba-printer-finder/config.ini).
+
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use glib::{KeyFile, KeyFileFlags};
+use url::Url;
+use crate::cups::{PrinterAuthMode, DEFAULT_TIMEOUT_MS};
+use crate::smb::{SambaAuthMode, SambaConnectionOptions, SambaEncryption, SambaProtocol, SambaSigning};
+
+const PRINTER_GROUP: &str = "printer";
+const CUPS_GROUP: &str = "cups";
+const BOOKMARKS_GROUP: &str = "bookmarks";
+const SERVER_GROUP_PREFIX: &str = "server ";
+
+// Settings of one server are stored in the group "server <name>"
+fn server_group(server: &str) -> String {
+    format!("{}{}", SERVER_GROUP_PREFIX, server.to_lowercase())
+}
+
+// A server the user logged in to, offered again in the login dialog
+#[derive(Debug, Clone, Default)]
+pub struct SavedServer {
+    // Address as typed in the login dialog
+    pub address: String,
+    pub domain: String,
+    pub username: String,
+    pub auth_mode: SambaAuthMode,
+    pub options: SambaConnectionOptions,
+    // Folder that was open when the user left the server
+    pub last_path: Option<Url>,
+    // Unix timestamp of the last login, the most recent server comes first
+    pub last_used: i64,
+}
+
+pub struct Config {
+    key_file: KeyFile,
+    path: PathBuf,
+}
+
+impl Config {
+    // Load the config file, a missing or broken file results in the default settings
+    pub fn load() -> Self {
+        let path = glib::user_config_dir()
+            .join("samba-printer-finder")
+            .join("config.ini");
+
+        let key_file = KeyFile::new();
+        let _ = key_file.load_from_file(&path, KeyFileFlags::KEEP_COMMENTS);
+
+        Config { key_file, path }
+    }
+
+    pub fn save(&self) -> Result<(), glib::Error> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                glib::Error::new(glib::FileError::Failed, &format!("Can't create {}: {}", dir.display(), e))
+            })?;
+        }
+
+        self.key_file.save_to_file(&self.path)
+    }
+
+    // How new printers authenticate against the SMB server, the last choice of the user
+    pub fn printer_auth_mode(&self) -> PrinterAuthMode {
+        self.key_file.string(PRINTER_GROUP, "auth-mode")
+            .ok()
+            .and_then(|mode| PrinterAuthMode::from_key(&mode))
+            .unwrap_or_default()
+    }
+
+    pub fn set_printer_auth_mode(&self, mode: PrinterAuthMode) {
+        self.key_file.set_string(PRINTER_GROUP, "auth-mode", mode.key());
+    }
+
+    // Timeout for connecting to CUPS and for each request, in milliseconds
+    pub fn cups_timeout_ms(&self) -> i32 {
+        self.key_file.integer(CUPS_GROUP, "timeout-ms")
+            .ok()
+            .filter(|timeout| *timeout > 0)
+            .unwrap_or(DEFAULT_TIMEOUT_MS)
+    }
+
+    // Connection options the user chose for `server`, the defaults if there are none
+    pub fn connection_options(&self, server: &str) -> SambaConnectionOptions {
+        let group = server_group(server);
+        let string = |key: &str| self.key_file.string(&group, key).ok();
+
+        SambaConnectionOptions {
+            min_protocol: string("min-protocol").and_then(|v| SambaProtocol::from_key(&v)).unwrap_or_default(),
+            max_protocol: string("max-protocol").and_then(|v| SambaProtocol::from_key(&v)).unwrap_or_default(),
+            signing: string("signing").and_then(|v| SambaSigning::from_key(&v)).unwrap_or_default(),
+            encryption: string("encryption").and_then(|v| SambaEncryption::from_key(&v)).unwrap_or_default(),
+            port: self.key_file.integer(&group, "port").ok().and_then(|v| u16::try_from(v).ok()).unwrap_or_default(),
+            timeout_ms: self.key_file.uint64(&group, "timeout-ms").ok().and_then(|v| u32::try_from(v).ok()).unwrap_or_default(),
+        }
+    }
+
+    pub fn set_connection_options(&self, server: &str, options: &SambaConnectionOptions) {
+        let group = server_group(server);
+
+        self.key_file.set_string(&group, "min-protocol", options.min_protocol.key());
+        self.key_file.set_string(&group, "max-protocol", options.max_protocol.key());
+        self.key_file.set_string(&group, "signing", options.signing.key());
+        self.key_file.set_string(&group, "encryption", options.encryption.key());
+        self.key_file.set_integer(&group, "port", options.port as i32);
+        self.key_file.set_uint64(&group, "timeout-ms", options.timeout_ms as u64);
+    }
+
+    // All servers the user logged in to, the most recently used first
+    pub fn saved_servers(&self) -> Vec<SavedServer> {
+        let mut servers: Vec<SavedServer> = self.key_file.groups()
+            .iter()
+            .filter_map(|group| group.as_str().strip_prefix(SERVER_GROUP_PREFIX).map(str::to_string))
+            .filter_map(|server| self.saved_server(&server))
+            .collect();
+
+        servers.sort_by_key(|server| std::cmp::Reverse(server.last_used));
+        servers
+    }
+
+    // The saved login of `server`, None if the user never logged in to it
+    pub fn saved_server(&self, server: &str) -> Option<SavedServer> {
+        let group = server_group(server);
+        let string = |key: &str| self.key_file.string(&group, key).ok().map(|v| v.to_string());
+
+        Some(SavedServer {
+            address: string("address")?,
+            domain: string("domain").unwrap_or_default(),
+            username: string("username").unwrap_or_default(),
+            auth_mode: string("auth-mode").and_then(|v| SambaAuthMode::from_key(&v)).unwrap_or_default(),
+            options: self.connection_options(server),
+            last_path: string("last-path").and_then(|v| Url::parse(&v).ok()),
+            last_used: self.key_file.int64(&group, "last-used").unwrap_or_default(),
+        })
+    }
+
+    pub fn save_server(&self, server: &str, saved: &SavedServer) {
+        let group = server_group(server);
+
+        self.key_file.set_string(&group, "address", &saved.address);
+        self.key_file.set_string(&group, "domain", &saved.domain);
+        self.key_file.set_string(&group, "username", &saved.username);
+        self.key_file.set_string(&group, "auth-mode", saved.auth_mode.key());
+        if let Some(last_path) = &saved.last_path {
+            self.key_file.set_string(&group, "last-path", last_path.as_str());
+        }
+        self.key_file.set_int64(&group, "last-used", saved.last_used);
+        self.set_connection_options(server, &saved.options);
+    }
+
+    // Remember the folder that is open on a saved server
+    pub fn set_last_path(&self, server: &str, path: &Url) {
+        let group = server_group(server);
+        if self.key_file.has_group(&group) {
+            self.key_file.set_string(&group, "last-path", path.as_str());
+        }
+    }
+
+    // Bookmarked folders, in the order they were added
+    pub fn bookmarks(&self) -> Vec<Url> {
+        let mut keys: Vec<(u32, String)> = self.key_file.keys(BOOKMARKS_GROUP)
+            .map(|keys| keys.iter()
+                .filter_map(|key| key.as_str().parse::<u32>().ok().map(|i| (i, key.to_string())))
+                .collect())
+            .unwrap_or_default();
+        keys.sort();
+
+        keys.iter()
+            .filter_map(|(_, key)| self.key_file.string(BOOKMARKS_GROUP, key).ok())
+            .filter_map(|url| Url::parse(&url).ok())
+            .collect()
+    }
+
+    // Replace all bookmarks, they are stored as numbered keys
+    pub fn set_bookmarks(&self, bookmarks: &[Url]) {
+        let _ = self.key_file.remove_group(BOOKMARKS_GROUP);
+        for (i, url) in bookmarks.iter().enumerate() {
+            self.key_file.set_string(BOOKMARKS_GROUP, &i.to_string(), url.as_str());
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::ffi::CString;
 use std::ptr::null_mut;
-use libc::{c_char, c_int};
+use std::sync::atomic::AtomicI32;
+use libc::{c_char, c_int, c_void};
 use crate::c_helper_fun::c_str_to_string;
 use crate::cups::enums::http_encryption::HttpEncryption;
 use crate::cups::ipp::IppRaw;
@@ -33,6 +34,15 @@ unsafe extern "C" {
         resource: *const c_char,
     ) -> *mut IppRaw;
 
+    fn httpReconnect2(http: *mut HttpT, msec: c_int, cancel: *mut c_int) -> c_int;
+    fn httpError(http: *mut HttpT) -> c_int;
+    fn httpSetTimeout(
+        http: *mut HttpT,
+        timeout: f64,
+        cb: Option<unsafe extern "C" fn(*mut HttpT, *mut c_void) -> c_int>,
+        user_data: *mut c_void,
+    );
+
     fn cupsLastError() -> IppStatus;
     fn cupsLastErrorString() -> *const c_char;
     fn httpClose(http: *mut HttpT);
@@ -55,13 +65,14 @@ pub fn http_connect2(
     encryption: HttpEncryption,
     blocking: bool,
     msec: i32,
-    cancel: Option<&mut i32>,
+    cancel: Option<&AtomicI32>,
 ) -> Option<*mut HttpT> {
     let c_host = CString::new(host).ok()?;
     let addr = addrlist.unwrap_or(null_mut());
     let blocking_c = if blocking { 1 } else { 0 };
+    // CUPS polls the flag while connecting, so it may be set from another thread
     let cancel_ptr = match cancel {
-        Some(r) => r as *mut i32,
+        Some(r) => r.as_ptr(),
         None => null_mut(),
     };
 
@@ -107,6 +118,26 @@ pub fn cups_do_request(
     }
 }
 
+// Reconnect an existing connection, returns true on success
+pub fn http_reconnect2(http: *mut HttpT, msec: i32, cancel: Option<&AtomicI32>) -> bool {
+    let cancel_ptr = match cancel {
+        Some(r) => r.as_ptr(),
+        None => null_mut(),
+    };
+
+    unsafe { httpReconnect2(http, msec as c_int, cancel_ptr as *mut c_int) == 0 }
+}
+
+// Give up waiting for an answer of the server after `msec`, instead of waiting forever
+pub fn http_set_timeout(http: *mut HttpT, msec: i32) {
+    unsafe { httpSetTimeout(http, msec as f64 / 1000.0, None, null_mut()) }
+}
+
+// Last errno of the connection, 0 if there was no error
+pub fn http_error(http: *mut HttpT) -> i32 {
+    unsafe { httpError(http) as i32 }
+}
+
 pub fn cups_last_error() -> IppStatus {
     unsafe { cupsLastError() }
 }
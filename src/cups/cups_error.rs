@@ -0,0 +1,36 @@
+use std::fmt::{Display, Formatter};
+use crate::cups::c_interop::{cups_last_error, cups_last_error_string};
+use crate::cups::enums::ipp_operations::IppOp;
+use crate::cups::enums::ipp_status::IppStatus;
+
+// Error of a CUPS request, with the operation that failed
+#[derive(Debug, Clone)]
+pub struct CupsError {
+    pub operation: IppOp,
+    // IppStatus::CupsInvalid if the error happened before the request was sent
+    pub status: IppStatus,
+    // cupsLastErrorString or a description of the local error
+    pub message: String,
+}
+
+impl CupsError {
+    // Error of the last CUPS request of this thread
+    pub(crate) fn last(operation: IppOp) -> Self {
+        let status = cups_last_error();
+        let message = cups_last_error_string().unwrap_or_else(|| format!("{:?}", status));
+        CupsError { operation, status, message }
+    }
+
+    // Error detected before the request was sent to CUPS
+    pub(crate) fn local(operation: IppOp, message: &str) -> Self {
+        CupsError { operation, status: IppStatus::CupsInvalid, message: message.to_string() }
+    }
+}
+
+impl Display for CupsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} failed: {} ({:?})", self.operation, self.message, self.status)
+    }
+}
+
+impl std::error::Error for CupsError {}
@@ -0,0 +1,146 @@
+use url::Url;
+use zeroize::Zeroizing;
+use crate::smb::SambaPassword;
+
+// The device-uri of a printer queue using the smb backend of CUPS (smbspool):
+//  - smb://server[:port]/share
+//  - smb://DOMAIN/server[:port]/share, CUPS asks for user and password when a job is printed
+//  - smb://user[:password]@[DOMAIN/]server[:port]/share, the credentials are stored in the queue
+// smbspool only splits at @ : and /, so the domain is always part of the path.
+// smb://DOMAIN;user@server/share is still read, older versions wrote queues that way.
+// Every component is percent-encoded on its own, so @ : ; / in a password or share name
+// can't be taken for a separator by the backend.
+#[derive(Debug, Default)]
+pub struct SmbDeviceUri {
+    pub workgroup: String,
+    pub username: String,
+    pub password: SambaPassword,
+    pub server: String,
+    pub port: Option<u16>,
+    pub share: String,
+}
+
+impl SmbDeviceUri {
+    // Server and share of a printer found in the browser, without credentials.
+    // None if the URL doesn't point to a share.
+    pub fn for_printer(url: &Url) -> Option<Self> {
+        let server = url.host_str().filter(|host| !host.is_empty())?;
+
+        let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+        let share = match segments.as_slice() {
+            [share] => url_escape::decode(share).to_string(),
+            _ => return None,
+        };
+
+        Some(SmbDeviceUri {
+            server: decode_host(server),
+            port: url.port(),
+            share,
+            ..SmbDeviceUri::default()
+        })
+    }
+
+    // Parse a device-uri written by to_uri, by hand or by older versions in one of the forms above.
+    // None if it is no smb:// URI of a printer.
+    pub fn parse(uri: &str) -> Option<Self> {
+        let rest = uri.strip_prefix("smb://")?;
+
+        // The encoded components contain no @, so the last one ends the credentials
+        let (userinfo, rest) = match rest.rfind('@') {
+            Some(i) => (Some(&rest[..i]), &rest[i + 1..]),
+            None => (None, rest),
+        };
+
+        let mut device_uri = SmbDeviceUri::default();
+
+        if let Some(userinfo) = userinfo {
+            let (user, password) = match userinfo.split_once(':') {
+                Some((user, password)) => (user, Some(password)),
+                None => (userinfo, None),
+            };
+            let (workgroup, user) = user.split_once(';').unwrap_or(("", user));
+
+            device_uri.workgroup = url_escape::decode(workgroup).to_string();
+            device_uri.username = url_escape::decode(user).to_string();
+            if let Some(password) = password {
+                device_uri.password = SambaPassword::new(url_escape::decode(password).into_owned());
+            }
+        }
+
+        let parts: Vec<&str> = rest.split('/').filter(|s| !s.is_empty()).collect();
+        let (host_port, share) = match parts.as_slice() {
+            [host_port, share] => (*host_port, *share),
+            // A workgroup in the path only if none was given with the user
+            [workgroup, host_port, share] if device_uri.workgroup.is_empty() => {
+                device_uri.workgroup = url_escape::decode(workgroup).to_string();
+                (*host_port, *share)
+            }
+            _ => return None,
+        };
+
+        // IPv6 literals keep their brackets, the port follows the closing one
+        let port_start = host_port.rfind(']').unwrap_or(0);
+        let (host, port) = match host_port[port_start..].rfind(':') {
+            Some(i) => (&host_port[..port_start + i], Some(host_port[port_start + i + 1..].parse::<u16>().ok()?)),
+            None => (host_port, None),
+        };
+
+        if host.is_empty() {
+            return None;
+        }
+
+        device_uri.server = decode_host(host);
+        device_uri.port = port;
+        device_uri.share = url_escape::decode(share).to_string();
+
+        Some(device_uri)
+    }
+
+    // Whether both point to the same share, credentials and domain don't matter.
+    // Names are compared like Windows does, without case. A missing port matches any port.
+    pub fn is_same_share(&self, other: &SmbDeviceUri) -> bool {
+        self.server.to_lowercase() == other.server.to_lowercase()
+            && self.share.to_lowercase() == other.share.to_lowercase()
+            && (self.port.is_none() || other.port.is_none() || self.port == other.port)
+    }
+
+    // The URI as sent to CUPS, it may contain the password and is wiped when dropped
+    pub fn to_uri(&self) -> Zeroizing<String> {
+        let mut uri = Zeroizing::new(String::from("smb://"));
+
+        if !self.username.is_empty() {
+            uri.push_str(&url_escape::encode_component(&self.username));
+            if !self.password.is_empty() {
+                uri.push(':');
+                uri.push_str(&Zeroizing::new(url_escape::encode_component(self.password.as_str()).into_owned()));
+            }
+            uri.push('@');
+        }
+
+        if !self.workgroup.is_empty() {
+            uri.push_str(&url_escape::encode_component(&self.workgroup));
+            uri.push('/');
+        }
+
+        // IPv6 literals can't be encoded, their brackets and colons are part of the syntax
+        if self.server.starts_with('[') {
+            uri.push_str(&self.server);
+        } else {
+            uri.push_str(&url_escape::encode_component(&self.server));
+        }
+
+        if let Some(port) = self.port {
+            uri.push_str(&format!(":{}", port));
+        }
+
+        uri.push('/');
+        uri.push_str(&url_escape::encode_component(&self.share));
+
+        uri
+    }
+}
+
+// Hosts of smb:// URLs are percent-encoded if they contain anything but ASCII
+fn decode_host(host: &str) -> String {
+    if host.starts_with('[') { host.to_string() } else { url_escape::decode(host).to_string() }
+}
@@ -23,6 +23,15 @@ unsafe extern "C" {
         language: *const c_char,
         value: *const c_char,
     ) -> *mut IppAttribute;
+    fn ippAddStrings(
+        ipp: *mut IppRaw,
+        group: IPPTag,
+        value_tag: IPPTag,
+        name: *const c_char,
+        num_values: c_int,
+        language: *const c_char,
+        values: *const *const c_char,
+    ) -> *mut IppAttribute;
 
     fn ippFirstAttribute(ipp: *mut IppRaw) -> *mut IppAttribute;
     fn ippNextAttribute(ipp: *mut IppRaw) -> *mut IppAttribute;
@@ -84,6 +93,41 @@ impl Ipp {
         }
     }
 
+    // Add an attribute with several values (1setOf)
+    pub fn add_strings(
+        &self,
+        group: IPPTag,
+        value_tag: IPPTag,
+        name: &str,
+        language: Option<&str>,
+        values: &[&str],
+    ) -> Option<*mut IppAttribute> {
+        let c_name = CString::new(name).ok()?;
+        let c_language = language.and_then(|s| CString::new(s).ok());
+        let c_values = values.iter()
+            .map(|v| CString::new(*v).ok())
+            .collect::<Option<Vec<CString>>>()?;
+        let c_value_ptrs: Vec<*const c_char> = c_values.iter().map(|v| v.as_ptr()).collect();
+
+        let res = unsafe {
+            ippAddStrings(
+                self.raw,
+                group,
+                value_tag,
+                c_name.as_ptr(),
+                c_value_ptrs.len() as c_int,
+                c_language.as_ref().map_or(null(), |s| s.as_ptr()),
+                c_value_ptrs.as_ptr(),
+            )
+        };
+
+        if res.is_null() {
+            None
+        } else {
+            Some(res)
+        }
+    }
+
     pub fn get_first_attribute(&mut self) -> Option<*mut IppAttribute> {
         let res = unsafe { ippFirstAttribute(self.raw) };
         if res.is_null() {
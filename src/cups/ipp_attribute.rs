@@ -10,6 +10,9 @@ pub struct IppAttribute { _private: [u8; 0] }
 unsafe extern "C" {
     fn ippGetName(attr: *mut IppAttribute) -> *const c_char;
     fn ippGetGroupTag(attr: *mut IppAttribute) -> IPPTag;
+    fn ippGetCount(attr: *mut IppAttribute) -> c_int;
+    fn ippGetInteger(attr: *mut IppAttribute, element: c_int) -> c_int;
+    fn ippGetBoolean(attr: *mut IppAttribute, element: c_int) -> c_int;
     fn ippGetString(
         attr: *mut IppAttribute,
         element: c_int,
@@ -23,13 +26,36 @@ impl IppAttribute {
     }
 
     pub fn get_string(&mut self) -> Option<String> {
+        self.get_string_at(0)
+    }
+
+    // Number of values, more than one for 1setOf attributes
+    pub fn get_count(&mut self) -> usize {
+        unsafe { ippGetCount(self).max(0) as usize }
+    }
+
+    pub fn get_string_at(&mut self, element: usize) -> Option<String> {
         let res_ptr = unsafe {
-            ippGetString(self, 0, null_mut())
+            ippGetString(self, element as c_int, null_mut())
         };
 
         c_str_to_string(res_ptr)
     }
 
+    // Value of an integer or enum attribute
+    pub fn get_integer(&mut self) -> i32 {
+        unsafe { ippGetInteger(self, 0) }
+    }
+
+    pub fn get_boolean(&mut self) -> bool {
+        unsafe { ippGetBoolean(self, 0) != 0 }
+    }
+
+    // All values of a 1setOf attribute
+    pub fn get_strings(&mut self) -> Vec<String> {
+        (0..self.get_count()).filter_map(|element| self.get_string_at(element)).collect()
+    }
+
     pub fn get_name(&mut self) -> Option<String> {
         let name_ptr = unsafe { ippGetName(self) };
         c_str_to_string(name_ptr)
@@ -1,26 +1,51 @@
 mod c_interop;
+mod device_uri;
+mod cups_error;
 mod ipp;
 mod ipp_attribute;
+mod queue_name;
 mod enums;
 
-use c_interop::{cups_do_request, cups_last_error, cups_last_error_string, cups_server, http_close, http_connect2, ipp_port, HttpT};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use c_interop::{cups_do_request, cups_last_error, cups_server, http_close, http_connect2, http_error, http_reconnect2, http_set_timeout, ipp_port, HttpT};
 use enums::http_encryption::HttpEncryption;
 use enums::ipp_operations::IppOp::CupsAddModifyPrinter;
+use enums::ipp_status::IppStatus;
 use enums::ipp_status::IppStatus::OkEventsComplete;
 use enums::ipp_tag::IPPTag;
 use enums::protocol_families::PF;
 use url::Url;
+use zeroize::Zeroizing;
 use ipp::Ipp;
 use enums::ipp_operations::IppOp;
 use crate::gui::printer_setup_dialog::PrinterSetupResult;
-use crate::smb::SambaCredentials;
+use crate::smb::{SambaAuthMode, SambaCredentials, SambaPassword};
 
-#[derive(Debug, Clone)]
+pub use cups_error::CupsError;
+pub use device_uri::SmbDeviceUri;
+pub use queue_name::{suggest_queue_name, unique_queue_name, validate_queue_name, InvalidQueueName, MAX_QUEUE_NAME_LEN};
+
+// Default timeout for connecting to the CUPS server and waiting for its answers
+pub const DEFAULT_TIMEOUT_MS: i32 = 30000;
+
+// Owns the connection to the CUPS server, which is closed when the manager is dropped
+#[derive(Debug)]
 pub(crate) struct CupsManager {
     http_t: *mut HttpT,
+    // Held while a request uses `http_t`, requests of several threads are sent one after the other
+    http_lock: Mutex<()>,
+    timeout_ms: i32,
     pub ppds: Vec<PpdInfo>,
 }
 
+// The connection is made on a background thread and then handed over to the GTK main loop.
+// Requests may run on other threads as well, every use of the connection goes through do_request,
+// which holds `http_lock`.
+unsafe impl Send for CupsManager {}
+unsafe impl Sync for CupsManager {}
+
 #[derive(Debug, Default, Clone)]
 pub struct PpdInfo {
     // PPD file name
@@ -33,10 +58,173 @@ pub struct PpdInfo {
     pub product: String,
 }
 
+// How the CUPS smb backend authenticates against the print server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrinterAuthMode {
+    // Username and password are part of the device-uri, readable by everyone who can list the queues
+    #[default]
+    StoreInUri,
+    // auth-info-required=username,password, the credentials are asked for when printing
+    UsernamePassword,
+    // auth-info-required=negotiate, uses the Kerberos ticket of the printing user
+    Negotiate,
+    // No credentials at all, for servers that allow guest access
+    Guest,
+}
+
+impl PrinterAuthMode {
+    pub const ALL: [PrinterAuthMode; 4] = [
+        PrinterAuthMode::StoreInUri,
+        PrinterAuthMode::UsernamePassword,
+        PrinterAuthMode::Negotiate,
+        PrinterAuthMode::Guest,
+    ];
+
+    // Name used in the config file
+    pub fn key(&self) -> &'static str {
+        match self {
+            PrinterAuthMode::StoreInUri => "store-in-uri",
+            PrinterAuthMode::UsernamePassword => "username-password",
+            PrinterAuthMode::Negotiate => "negotiate",
+            PrinterAuthMode::Guest => "guest",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        PrinterAuthMode::ALL.iter().copied().find(|mode| mode.key() == key)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PrinterAuthMode::StoreInUri => "Save username and password in the printer",
+            PrinterAuthMode::UsernamePassword => "Ask for username and password when printing",
+            PrinterAuthMode::Negotiate => "Use the Kerberos ticket of the user (negotiate)",
+            PrinterAuthMode::Guest => "No credentials (guest access)",
+        }
+    }
+
+    // Values of the auth-info-required attribute, empty if no attribute is needed
+    fn auth_info_required(&self) -> &'static [&'static str] {
+        match self {
+            PrinterAuthMode::StoreInUri | PrinterAuthMode::Guest => &[],
+            PrinterAuthMode::UsernamePassword => &["username", "password"],
+            PrinterAuthMode::Negotiate => &["negotiate"],
+        }
+    }
+}
+
+// A queue that was added or modified in CUPS
+#[derive(Debug, Clone)]
+pub struct InstalledPrinter {
+    // Final queue name
+    pub name: String,
+    // printer-uri of the queue, e.g. ipp://localhost/printers/name
+    pub printer_uri: String,
+}
+
+// printer-state of RFC 8011
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrinterState {
+    Idle,
+    Processing,
+    Stopped,
+    #[default]
+    Unknown,
+}
+
+impl PrinterState {
+    fn from_ipp(value: i32) -> Self {
+        match value {
+            3 => PrinterState::Idle,
+            4 => PrinterState::Processing,
+            5 => PrinterState::Stopped,
+            _ => PrinterState::Unknown,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PrinterState::Idle => "Idle",
+            PrinterState::Processing => "Printing",
+            PrinterState::Stopped => "Stopped",
+            PrinterState::Unknown => "Unknown",
+        }
+    }
+}
+
+// A queue of the CUPS server, as listed by CUPS-Get-Printers
+#[derive(Debug, Default)]
+pub struct PrinterQueue {
+    pub name: String,
+    // Whether the device-uri starts with smb://
+    pub smb_backend: bool,
+    // Target of queues with the smb backend, None if the device-uri can't be read.
+    // The password of the device-uri is not kept.
+    pub share: Option<SmbDeviceUri>,
+    pub state: PrinterState,
+    // printer-state-reasons without "none", e.g. "offline-report" or "paused"
+    pub state_reasons: Vec<String>,
+    pub accepting_jobs: bool,
+    pub info: String,
+    pub location: String,
+    // printer-make-and-model, the driver the queue uses
+    pub make_and_model: String,
+}
+
+impl PrinterQueue {
+    // Whether the queue prints to the share at `url`, whatever credentials its device-uri contains
+    pub fn is_for_share(&self, url: &Url) -> bool {
+        match (&self.share, SmbDeviceUri::for_printer(url)) {
+            (Some(queue), Some(share)) => queue.is_same_share(&share),
+            _ => false,
+        }
+    }
+}
+
+// Settings of an installed queue, as read with Get-Printer-Attributes
+#[derive(Debug, Default)]
+pub struct PrinterDetails {
+    pub name: String,
+    pub info: String,
+    pub location: String,
+    pub make_and_model: String,
+    // Only reported by some CUPS versions, the driver is matched by make and model otherwise
+    pub ppd_name: String,
+    // None if the queue doesn't use the smb backend
+    pub device_uri: Option<SmbDeviceUri>,
+    pub auth_info_required: Vec<String>,
+}
+
+impl PrinterDetails {
+    // How the queue authenticates, derived from auth-info-required and the device-uri
+    pub fn auth_mode(&self) -> PrinterAuthMode {
+        let required = |value: &str| self.auth_info_required.iter().any(|v| v == value);
+
+        if required("negotiate") {
+            PrinterAuthMode::Negotiate
+        } else if required("password") {
+            PrinterAuthMode::UsernamePassword
+        } else if self.device_uri.as_ref().is_some_and(|uri| !uri.username.is_empty()) {
+            PrinterAuthMode::StoreInUri
+        } else {
+            PrinterAuthMode::Guest
+        }
+    }
+
+    pub fn uses_driver(&self, ppd: &PpdInfo) -> bool {
+        match self.ppd_name.is_empty() {
+            true => ppd.make_and_model == self.make_and_model,
+            false => ppd.name == self.ppd_name,
+        }
+    }
+}
+
 // Handle the Interaction between Cups and the rest of the application.
 impl CupsManager {
-    pub fn new() -> Self {
-        let cups_server = cups_server().unwrap_or(String::new());
+    // Connect to the configured CUPS server and load the available drivers.
+    // Blocks for up to `timeout_ms` per step, setting `cancel` to a non-zero value aborts connecting.
+    pub fn connect(timeout_ms: i32, cancel: &AtomicI32) -> Result<Self, CupsError> {
+        let cups_server = cups_server().unwrap_or_default();
         let ipp_port = ipp_port();
 
         let http_t = http_connect2(
@@ -46,102 +234,362 @@ impl CupsManager {
             PF::Unspec,
             HttpEncryption::IfRequested,
             true,
-            30000,
-            None,
+            timeout_ms,
+            Some(cancel),
         );
 
-        let http_t = http_t.expect("Failed to connect to CUPS server");
+        let http_t = http_t.ok_or_else(|| CupsError::local(
+            IppOp::CupsNone,
+            &format!("Failed to connect to CUPS server at {}:{}", cups_server, ipp_port),
+        ))?;
+
+        // Requests to a hung cupsd fail after the timeout instead of blocking forever
+        http_set_timeout(http_t, timeout_ms);
 
-        if http_t.is_null() {
-            panic!("Failed to connect to CUPS server at {}:{}", cups_server, ipp_port);
+        let mut this = CupsManager { http_t, http_lock: Mutex::new(()), timeout_ms, ppds: Vec::new() };
+
+        if cancel.load(Ordering::Relaxed) != 0 {
+            return Err(CupsError::local(IppOp::CupsNone, "Connecting to CUPS was cancelled"));
         }
+        this.fetch_ppds()?;
+
+        Ok(this)
+    }
+
+    // Same as connect, but on a background thread so the GTK main loop keeps running
+    pub async fn connect_in_background(timeout_ms: i32, cancel: Arc<AtomicI32>) -> Result<Self, CupsError> {
+        let (tx, rx) = oneshot::channel();
 
-        let mut this = CupsManager { http_t, ppds: Vec::new() };
-        this.fetch_ppds();
+        thread::Builder::new()
+            .name("cups-connect".to_string())
+            .spawn(move || {
+                let _ = tx.send(CupsManager::connect(timeout_ms, &cancel));
+            })
+            .map_err(|e| CupsError::local(IppOp::CupsNone, &format!("Can't start the CUPS connection thread: {}", e)))?;
 
-        this
+        rx.await.unwrap_or_else(|_| Err(CupsError::local(IppOp::CupsNone, "The CUPS connection thread stopped")))
     }
 
-    pub fn connect_to_printer(&self, creds: SambaCredentials, url: &Url, setup: &PrinterSetupResult, ppd: Option<&PpdInfo>) {
-        let request = Ipp::new(CupsAddModifyPrinter);
-        let mut smb_printer_uri = url.clone();
+    // printer_queues on a background thread, a slow cupsd doesn't block the GTK main loop
+    pub async fn printer_queues_in_background(self: Arc<Self>) -> Result<Vec<PrinterQueue>, CupsError> {
+        self.in_background(IppOp::CupsGetPrinters, |manager| manager.printer_queues()).await
+    }
 
-        smb_printer_uri
-            .set_username(&*creds.username)
-            .expect("Unable to set username");
+    // printer_details on a background thread
+    pub async fn printer_details_in_background(self: Arc<Self>, name: String) -> Result<PrinterDetails, CupsError> {
+        self.in_background(IppOp::GetPrinterAttributes, move |manager| manager.printer_details(&name)).await
+    }
 
-        smb_printer_uri
-            .set_password(Option::from(&*creds.password))
-            .expect("Unable to set password");
+    // connect_to_printer on a background thread, the dialog stays busy until CUPS answered
+    pub async fn connect_to_printer_in_background(self: Arc<Self>, creds: Arc<SambaCredentials>, url: Url, setup: PrinterSetupResult, ppd: Option<PpdInfo>) -> Result<InstalledPrinter, CupsError> {
+        self.in_background(CupsAddModifyPrinter, move |manager| manager.connect_to_printer(&creds, &url, &setup, ppd.as_ref())).await
+    }
 
-        // Create printer URI and escape printer name
-        let mut printer_ipp_uri = Url::parse("ipp://localhost/printers/").unwrap();
-        printer_ipp_uri = printer_ipp_uri.join(&setup.printer_name).expect("Unable to create printer URI");
+    // update_printer on a background thread
+    pub async fn update_printer_in_background(self: Arc<Self>, creds: Arc<SambaCredentials>, url: Url, current: Arc<PrinterDetails>, setup: PrinterSetupResult, ppd: Option<PpdInfo>) -> Result<InstalledPrinter, CupsError> {
+        self.in_background(CupsAddModifyPrinter, move |manager| manager.update_printer(&creds, &url, &current, &setup, ppd.as_ref())).await
+    }
+
+    async fn in_background<T, F>(self: Arc<Self>, operation: IppOp, request: F) -> Result<T, CupsError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&CupsManager) -> Result<T, CupsError> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+
+        thread::Builder::new()
+            .name("cups-request".to_string())
+            .spawn(move || {
+                let _ = tx.send(request(&self));
+            })
+            .map_err(|e| CupsError::local(operation, &format!("Can't start the CUPS request thread: {}", e)))?;
+
+        rx.await.unwrap_or_else(|_| Err(CupsError::local(operation, "The CUPS request thread stopped")))
+    }
+
+    // Add or modify the printer queue
+    pub fn connect_to_printer(&self, creds: &SambaCredentials, url: &Url, setup: &PrinterSetupResult, ppd: Option<&PpdInfo>) -> Result<InstalledPrinter, CupsError> {
+        let auth_mode = effective_auth_mode(creds, setup);
+
+        // The device-uri may contain the password, it is wiped once the request was sent
+        let smb_printer_uri = smb_device_uri(creds, url, auth_mode)?;
+        let printer_ipp_uri = printer_ipp_uri(&setup.printer_name)?;
 
-        request.add_string(IPPTag::Operation, IPPTag::Uri,
-                           Option::from("printer-uri"), None, &printer_ipp_uri.to_string());
+        self.do_request(CupsAddModifyPrinter, "/admin/", || {
+            let request = Ipp::new(CupsAddModifyPrinter);
 
-        request.add_string(IPPTag::Printer, IPPTag::Uri,
-                       Option::from("device-uri"), None,
-                       &smb_printer_uri.to_string());
+            request.add_string(IPPTag::Operation, IPPTag::Uri,
+                               Option::from("printer-uri"), None, printer_ipp_uri.as_ref());
 
-        let location = url_escape::encode_fragment(&setup.location);
-        request.add_string(IPPTag::Printer, IPPTag::Name,
-                       Option::from("printer-location"), None,
-                       &location.to_string());
+            request.add_string(IPPTag::Printer, IPPTag::Uri,
+                               Option::from("device-uri"), None,
+                               &smb_printer_uri);
 
-        let description = url_escape::encode_fragment(&setup.description);
-        request.add_string(IPPTag::Printer, IPPTag::Name,
-                       Option::from("printer-info"), None,
-                       &description.to_string());
+            request.add_string(IPPTag::Printer, IPPTag::Name,
+                               Option::from("printer-location"), None,
+                               &setup.location);
 
-        if let Some(ppd) = ppd {
             request.add_string(IPPTag::Printer, IPPTag::Name,
-                           Option::from("ppd-name"), None,
-                           &ppd.name);
+                               Option::from("printer-info"), None,
+                               &setup.description);
+
+            if let Some(ppd) = ppd {
+                request.add_string(IPPTag::Printer, IPPTag::Name,
+                                   Option::from("ppd-name"), None,
+                                   &ppd.name);
+            }
+
+            let auth_info_required = auth_mode.auth_info_required();
+            if !auth_info_required.is_empty() {
+                request.add_strings(IPPTag::Printer, IPPTag::Keyword,
+                                    "auth-info-required", None,
+                                    auth_info_required);
+            }
+
+            request
+        })?;
+
+        Ok(InstalledPrinter {
+            name: setup.printer_name.clone(),
+            printer_uri: printer_ipp_uri.to_string(),
+        })
+    }
+
+    // Save the changes to an installed queue. Only the attributes that differ from `current` are sent,
+    // the driver and the device-uri (with the credentials) stay untouched unless they were changed.
+    pub fn update_printer(&self, creds: &SambaCredentials, url: &Url, current: &PrinterDetails, setup: &PrinterSetupResult, ppd: Option<&PpdInfo>) -> Result<InstalledPrinter, CupsError> {
+        let printer_ipp_uri = printer_ipp_uri(&current.name)?;
+
+        let description = Some(&setup.description).filter(|description| **description != current.info);
+        let location = Some(&setup.location).filter(|location| **location != current.location);
+        let ppd = ppd.filter(|ppd| !current.uses_driver(ppd));
+
+        // The dialog starts with the mode of the queue, which stays as long as the user keeps it.
+        // Another pick is written the way the login allows, the credentials only change if that
+        // mode differs from the one of the queue.
+        let auth_mode = match setup.auth_mode == current.auth_mode() {
+            true => current.auth_mode(),
+            false => effective_auth_mode(creds, setup),
+        };
+        let smb_printer_uri = match auth_mode != current.auth_mode() {
+            true => Some(smb_device_uri(creds, url, auth_mode)?),
+            false => None,
+        };
+
+        let installed = InstalledPrinter {
+            name: current.name.clone(),
+            printer_uri: printer_ipp_uri.to_string(),
+        };
+
+        if description.is_none() && location.is_none() && ppd.is_none() && smb_printer_uri.is_none() {
+            return Ok(installed);
         }
 
+        self.do_request(CupsAddModifyPrinter, "/admin/", || {
+            let request = Ipp::new(CupsAddModifyPrinter);
 
-        let response = cups_do_request(self.http_t, request.into_raw(), "/admin/");
+            request.add_string(IPPTag::Operation, IPPTag::Uri,
+                               Option::from("printer-uri"), None, printer_ipp_uri.as_ref());
 
-        let _response = response
-            .and_then(Ipp::from_raw);
+            if let Some(location) = location {
+                request.add_string(IPPTag::Printer, IPPTag::Name,
+                                   Option::from("printer-location"), None,
+                                   location);
+            }
 
-        if cups_last_error() > OkEventsComplete {
-            eprintln!("CUPS Error: {:?}", cups_last_error_string());
-        } else {
-            println!("Printer added/modified successfully.");
+            if let Some(description) = description {
+                request.add_string(IPPTag::Printer, IPPTag::Name,
+                                   Option::from("printer-info"), None,
+                                   description);
+            }
+
+            if let Some(ppd) = ppd {
+                request.add_string(IPPTag::Printer, IPPTag::Name,
+                                   Option::from("ppd-name"), None,
+                                   &ppd.name);
+            }
+
+            // Credentials in the URI and auth-info-required change together.
+            // "none" removes an auth-info-required the queue had before.
+            if let Some(smb_printer_uri) = &smb_printer_uri {
+                request.add_string(IPPTag::Printer, IPPTag::Uri,
+                                   Option::from("device-uri"), None,
+                                   smb_printer_uri);
+
+                let auth_info_required = match auth_mode.auth_info_required() {
+                    [] => &["none"],
+                    values => values,
+                };
+                request.add_strings(IPPTag::Printer, IPPTag::Keyword,
+                                    "auth-info-required", None,
+                                    auth_info_required);
+            }
+
+            request
+        })?;
+
+        Ok(installed)
+    }
+
+    // Settings of the installed queue `name`
+    pub fn printer_details(&self, name: &str) -> Result<PrinterDetails, CupsError> {
+        let operation = IppOp::GetPrinterAttributes;
+        let printer_ipp_uri = printer_ipp_uri(name)?;
+
+        let mut response = self.do_request(operation, "/", || {
+            let request = Ipp::new(operation);
+
+            request.add_string(IPPTag::Operation, IPPTag::Uri,
+                               Option::from("printer-uri"), None, printer_ipp_uri.as_ref());
+
+            request.add_strings(IPPTag::Operation, IPPTag::Keyword,
+                                "requested-attributes", None,
+                                &["printer-name", "printer-info", "printer-location", "printer-make-and-model",
+                                  "ppd-name", "device-uri", "auth-info-required"]);
+
+            request
+        })?;
+
+        let mut details = PrinterDetails { name: name.to_string(), ..PrinterDetails::default() };
+
+        let mut attr = response.get_first_attribute();
+        while let Some(attr_ptr) = attr {
+            if let Some(attr_ref) = unsafe { attr_ptr.as_mut() } {
+                if attr_ref.get_group_tag() == IPPTag::Printer {
+                    match attr_ref.get_name().as_deref() {
+                        Some("printer-name") => details.name = attr_ref.get_string().unwrap_or(details.name),
+                        Some("printer-info") => details.info = decode_legacy_text(attr_ref.get_string()),
+                        Some("printer-location") => details.location = decode_legacy_text(attr_ref.get_string()),
+                        Some("printer-make-and-model") => details.make_and_model = attr_ref.get_string().unwrap_or_default(),
+                        Some("ppd-name") => details.ppd_name = attr_ref.get_string().unwrap_or_default(),
+                        Some("device-uri") => {
+                            details.device_uri = attr_ref.get_string().and_then(|uri| SmbDeviceUri::parse(&Zeroizing::new(uri)));
+                        }
+                        Some("auth-info-required") => details.auth_info_required = attr_ref.get_strings(),
+                        _ => {}
+                    }
+                }
+            }
+
+            attr = response.get_next_attribute();
         }
+
+        Ok(details)
     }
 
-    fn fetch_ppds(&mut self) -> bool {
-        // CupsGetPpds is deprecated in CUPS 2.4 and later, but it seems to be the only way to get a list of available PPDs without parsing the filesystem directly, which is what CUPS does internally.
-        // The alternative would be to read the PPD files directly from the filesystem, but that would require us to know the exact location of the PPD files, which may not be consistent across different CUPS installations or versions.
-        // For now, we'll use CupsGetPpds and hope that it continues to be supported in future CUPS versions, but if it gets removed we may need to switch to a different approach, such as parsing the PPD files directly or using a different CUPS API if one becomes available.
-        let request = Ipp::new(IppOp::CupsGetPpds);
+    // All queues of the CUPS server, with or without smb backend
+    pub fn printer_queues(&self) -> Result<Vec<PrinterQueue>, CupsError> {
+        let operation = IppOp::CupsGetPrinters;
+        let mut response = self.do_request(operation, "/", || {
+            let request = Ipp::new(operation);
 
-        request.add_string(IPPTag::Operation, IPPTag::Uri, Option::from("printer-uri"),
-                       None, "ipp://localhost/",
-        );
+            request.add_strings(IPPTag::Operation, IPPTag::Keyword,
+                                "requested-attributes", None,
+                                &["printer-name", "device-uri", "printer-state", "printer-state-reasons",
+                                  "printer-is-accepting-jobs", "printer-info", "printer-location", "printer-make-and-model"]);
+
+            request
+        })?;
+
+        // The printers are separated by attributes without a name
+        let mut queues = Vec::new();
+        let mut current = PrinterQueue::default();
+
+        let mut attr = response.get_first_attribute();
+        while let Some(attr_ptr) = attr {
+            if let Some(attr_ref) = unsafe { attr_ptr.as_mut() } {
+                match attr_ref.get_name().as_deref() {
+                    Some("printer-name") => current.name = attr_ref.get_string().unwrap_or_default(),
+                    Some("device-uri") => {
+                        let uri = Zeroizing::new(attr_ref.get_string().unwrap_or_default());
+                        current.smb_backend = uri.starts_with("smb://");
+                        current.share = SmbDeviceUri::parse(&uri).map(|mut share| {
+                            share.password = SambaPassword::default();
+                            share
+                        });
+                    }
+                    Some("printer-state") => current.state = PrinterState::from_ipp(attr_ref.get_integer()),
+                    Some("printer-state-reasons") => {
+                        current.state_reasons = attr_ref.get_strings().into_iter().filter(|reason| reason != "none").collect();
+                    }
+                    Some("printer-is-accepting-jobs") => current.accepting_jobs = attr_ref.get_boolean(),
+                    Some("printer-info") => current.info = decode_legacy_text(attr_ref.get_string()),
+                    Some("printer-location") => current.location = decode_legacy_text(attr_ref.get_string()),
+                    Some("printer-make-and-model") => current.make_and_model = attr_ref.get_string().unwrap_or_default(),
+                    Some(_) => {}
+                    None => {
+                        if !current.name.is_empty() {
+                            queues.push(std::mem::take(&mut current));
+                        }
+                    }
+                }
+            }
 
-        let response = cups_do_request(self.http_t, request.into_raw(), "/");
+            attr = response.get_next_attribute();
+        }
 
-        if response.is_none() {
-            eprintln!("CUPS request failed");
-            return false;
+        if !current.name.is_empty() {
+            queues.push(current);
         }
 
-        let mut response = match response.and_then(Ipp::from_raw) {
-            Some(res) => res,
-            None => {
-                eprintln!("CUPS request failed");
-                return false;
+        Ok(queues)
+    }
+
+    // Send a request and wait for the response.
+    // cupsDoRequest frees the request, so it is created by `build` and built again if the
+    // connection was broken (e.g. cupsd restarted) and the request is retried after a reconnect.
+    fn do_request<F: Fn() -> Ipp>(&self, operation: IppOp, resource: &str, build: F) -> Result<Ipp, CupsError> {
+        let _connection = self.http_lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut reconnected = false;
+
+        loop {
+            let response = cups_do_request(self.http_t, build().into_raw(), resource)
+                .and_then(Ipp::from_raw);
+
+            match response {
+                Some(response) if cups_last_error() <= OkEventsComplete => return Ok(response),
+                Some(_) => return Err(CupsError::last(operation)),
+                None => {
+                    let error = CupsError::last(operation);
+
+                    if reconnected || !self.connection_broken() {
+                        return Err(error);
+                    }
+
+                    if !http_reconnect2(self.http_t, self.timeout_ms, None) {
+                        return Err(error);
+                    }
+                    reconnected = true;
+                }
             }
-        };
+        }
+    }
+
+    // cupsDoRequest reports a lost connection as internal error or unavailable service
+    fn connection_broken(&self) -> bool {
+        matches!(cups_last_error(), IppStatus::ErrorInternal | IppStatus::ErrorServiceUnavailable)
+            || http_error(self.http_t) != 0
+    }
+
+    fn fetch_ppds(&mut self) -> Result<(), CupsError> {
+        // CupsGetPpds is deprecated in CUPS 2.4 and later, but it seems to be the only way to get a list of available PPDs without parsing the filesystem directly, which is what CUPS does internally.
+        // The alternative would be to read the PPD files directly from the filesystem, but that would require us to know the exact location of the PPD files, which may not be consistent across different CUPS installations or versions.
+        // For now, we'll use CupsGetPpds and hope that it continues to be supported in future CUPS versions, but if it gets removed we may need to switch to a different approach, such as parsing the PPD files directly or using a different CUPS API if one becomes available.
+        let operation = IppOp::CupsGetPpds;
+        let mut response = self.do_request(operation, "/", || {
+            let request = Ipp::new(operation);
 
+            request.add_string(IPPTag::Operation, IPPTag::Uri, Option::from("printer-uri"),
+                           None, "ipp://localhost/",
+            );
+
+            request
+        })?;
+
+        self.ppds.clear();
         self.parse_response(&mut response);
 
-        true
+        Ok(())
     }
 
     // Internal function to parse the response from the CupsGetPpds into PpdInfo structs
@@ -190,4 +638,58 @@ impl Drop for CupsManager {
     fn drop(&mut self) {
         http_close(self.http_t);
     }
-}
\ No newline at end of file
+}
+
+// printer-info and printer-location are plain text attributes, CUPS shows them as they are sent.
+// Earlier versions percent-encoded them like a URL fragment, those queues are read back decoded,
+// so an unchanged description doesn't count as changed when the queue is edited.
+fn decode_legacy_text(value: Option<String>) -> String {
+    let value = value.unwrap_or_default();
+    url_escape::decode(&value).into_owned()
+}
+
+// Printers found with a Kerberos login have no password to store, they always use negotiate.
+// Printers found as guest get a device-uri without any credentials.
+fn effective_auth_mode(creds: &SambaCredentials, setup: &PrinterSetupResult) -> PrinterAuthMode {
+    match creds.auth_mode {
+        SambaAuthMode::Kerberos => PrinterAuthMode::Negotiate,
+        SambaAuthMode::Guest => PrinterAuthMode::Guest,
+        SambaAuthMode::Password => setup.auth_mode,
+    }
+}
+
+// The device-uri of the share at `url`, it may contain the password and is wiped when dropped
+fn smb_device_uri(creds: &SambaCredentials, url: &Url, auth_mode: PrinterAuthMode) -> Result<Zeroizing<String>, CupsError> {
+    let mut device_uri = SmbDeviceUri::for_printer(url)
+        .ok_or_else(|| CupsError::local(CupsAddModifyPrinter, "The printer has no valid SMB address"))?;
+
+    // The domain is needed by the smb backend to log in with a domain account, whoever provides the password
+    if auth_mode != PrinterAuthMode::Guest {
+        device_uri.workgroup = creds.workgroup.clone();
+    }
+
+    // Only store the credentials in the URI if the user asked for it.
+    // Otherwise CUPS asks for them (or uses Kerberos) when a job is printed.
+    if auth_mode == PrinterAuthMode::StoreInUri {
+        device_uri.username = creds.username.clone();
+        device_uri.password = SambaPassword::from(creds.password.as_str());
+    }
+
+    Ok(device_uri.to_uri())
+}
+
+// ipp://localhost/printers/<name>
+fn printer_ipp_uri(name: &str) -> Result<Url, CupsError> {
+    // A name CUPS rejects would also give a broken printer URI
+    validate_queue_name(name)
+        .map_err(|e| CupsError::local(CupsAddModifyPrinter, &e.to_string()))?;
+
+    // The name is one path segment, Url::join would take e.g. "HP:Floor2" for an absolute URL
+    let mut uri = Url::parse("ipp://localhost/printers")
+        .map_err(|_| CupsError::local(CupsAddModifyPrinter, "Unable to create the printer URI"))?;
+    uri.path_segments_mut()
+        .map_err(|_| CupsError::local(CupsAddModifyPrinter, "Unable to create the printer URI"))?
+        .push(name);
+
+    Ok(uri)
+}
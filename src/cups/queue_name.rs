@@ -0,0 +1,98 @@
+use std::fmt::{Display, Formatter};
+
+// CUPS rejects longer queue names (validate_name in scheduler/ipp.c counts bytes)
+pub const MAX_QUEUE_NAME_LEN: usize = 127;
+
+// Why CUPS would refuse a queue name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidQueueName {
+    Empty,
+    // Length in bytes
+    TooLong(usize),
+    Character(char),
+    // "." and ".." would be taken for path segments in the printer URI
+    OnlyDots,
+}
+
+impl Display for InvalidQueueName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidQueueName::Empty => write!(f, "The printer name must not be empty."),
+            InvalidQueueName::TooLong(len) => {
+                write!(f, "The printer name is {} bytes long, at most {} are allowed.", len, MAX_QUEUE_NAME_LEN)
+            }
+            InvalidQueueName::Character(c) if c.is_whitespace() => write!(f, "The printer name must not contain spaces."),
+            InvalidQueueName::Character(c) if c.is_control() => write!(f, "The printer name must not contain control characters."),
+            InvalidQueueName::Character(c) => write!(f, "The printer name must not contain \"{}\".", c),
+            InvalidQueueName::OnlyDots => write!(f, "The printer name must not consist of dots only."),
+        }
+    }
+}
+
+// The rules of the CUPS scheduler: no spaces, control characters or / \ ? ' " #.
+// An @ would make CUPS take the name for a queue on another server.
+// Other characters, including non-ASCII ones, are allowed, but not only dots.
+pub fn validate_queue_name(name: &str) -> Result<(), InvalidQueueName> {
+    if name.is_empty() {
+        return Err(InvalidQueueName::Empty);
+    }
+
+    if let Some(c) = name.chars().find(|c| !is_allowed(*c)) {
+        return Err(InvalidQueueName::Character(c));
+    }
+
+    if name.chars().all(|c| c == '.') {
+        return Err(InvalidQueueName::OnlyDots);
+    }
+
+    if name.len() > MAX_QUEUE_NAME_LEN {
+        return Err(InvalidQueueName::TooLong(name.len()));
+    }
+
+    Ok(())
+}
+
+// A valid queue name for a share, e.g. "printsrv01-Floor2_Color" for \\printsrv01\Floor2 Color.
+// Only ASCII letters, digits, - _ and . are kept, so the name is easy to type in lp -d as well.
+pub fn suggest_queue_name(server: &str, share: &str) -> String {
+    let mut name = String::new();
+
+    for c in format!("{}-{}", server, share).chars() {
+        let c = if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' };
+
+        // One _ for a run of replaced characters
+        if !(c == '_' && name.ends_with('_')) {
+            name.push(c);
+        }
+    }
+
+    let mut name = name.trim_matches('_').to_string();
+    name.truncate(MAX_QUEUE_NAME_LEN);
+
+    if name.is_empty() { "printer".to_string() } else { name }
+}
+
+// `name`, or `name` with a number appended if a queue of that name exists already.
+// CUPS compares queue names without case.
+pub fn unique_queue_name(name: &str, existing: &[String]) -> String {
+    let taken = |candidate: &str| existing.iter().any(|e| e.eq_ignore_ascii_case(candidate));
+    if !taken(name) {
+        return name.to_string();
+    }
+
+    (2..)
+        .map(|number| {
+            let suffix = format!("-{}", number);
+            let mut base = name.to_string();
+            while base.len() + suffix.len() > MAX_QUEUE_NAME_LEN {
+                base.pop();
+            }
+            base + &suffix
+        })
+        .find(|candidate| !taken(candidate))
+        .unwrap_or_default()
+}
+
+fn is_allowed(c: char) -> bool {
+    !c.is_whitespace() && !c.is_control() && !matches!(c, '/' | '\\' | '?' | '\'' | '"' | '#' | '@')
+}
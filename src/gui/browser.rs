@@ -0,0 +1,754 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use glib::MainContext;
+use gtk::gio::{ActionMap, ListStore, SimpleAction};
+use gtk::{prelude::*, Align, Box, Button, Entry, Label, MenuButton, Orientation, Popover, ScrolledWindow, Separator, PolicyType, Spinner};
+use url::{Position, Url};
+use crate::config::Config;
+use crate::cups::PrinterQueue;
+use crate::gui::error_reporter::ErrorReporter;
+use crate::gui::samba_entry_object::SambaEntryObject;
+use crate::smb::{parent_url, parse_location, SambaEntryType, SambaError, SambaLocation, SambaWorker};
+
+type PrinterHandler = std::boxed::Box<dyn Fn(SambaEntryObject)>;
+type LoginHandler = std::boxed::Box<dyn Fn(Option<SambaLocation>)>;
+type QueuesHandler = std::boxed::Box<dyn Fn(&[Rc<PrinterQueue>])>;
+type PendingLogin = std::boxed::Box<dyn FnOnce()>;
+
+// State of the SMB browser: the current connection, the list of entries, the history and the loading indicator.
+// Listings run on the SMB worker thread. Every navigation gets a new generation number and a result
+// is only applied if no other navigation (or a cancel) happened in the meantime.
+pub(crate) struct Browser {
+    list_store: ListStore,
+    reporter: Rc<ErrorReporter>,
+    connection: RefCell<Option<Rc<SambaWorker>>>,
+    generation: Cell<u64>,
+    // Queues of the CUPS server, to mark printers that are installed already
+    printer_queues: RefCell<Vec<Rc<PrinterQueue>>>,
+
+    // Navigation history
+    current: RefCell<Option<Url>>,
+    // Last folder listed on the server, written to the config when the server changes or the window closes
+    last_path: RefCell<Option<Url>>,
+    back_stack: RefCell<Vec<Url>>,
+    forward_stack: RefCell<Vec<Url>>,
+
+    // Called when a printer is opened, when a connection (or another user) is needed
+    // and when the queues of the CUPS server were loaded again
+    printer_handler: RefCell<Option<PrinterHandler>>,
+    login_handler: RefCell<Option<LoginHandler>>,
+    queues_handler: RefCell<Option<QueuesHandler>>,
+    // Runs once the first listing of the connection worked, e.g. saving the password.
+    // Creating a connection doesn't talk to the server, only a listing shows that the login is right.
+    pending_login: RefCell<Option<PendingLogin>>,
+
+    // Widgets
+    toolbar: Box,
+    path_bar: Box,
+    location_entry: Entry,
+    loading_bar: Box,
+    loading_label: Label,
+    spinner: Spinner,
+    bookmarks_popover: Popover,
+    bookmarks_list: Box,
+
+    // Actions, installed on the window with add_actions
+    back_action: SimpleAction,
+    forward_action: SimpleAction,
+    up_action: SimpleAction,
+    refresh_action: SimpleAction,
+    bookmark_action: SimpleAction,
+}
+
+impl Browser {
+    pub fn new(list_store: ListStore, reporter: Rc<ErrorReporter>) -> Rc<Self> {
+        // ---- Navigation toolbar ----
+        let toolbar = Box::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(6)
+            .build();
+
+        let back_button = navigation_button("go-previous-symbolic", "Back (Alt+Left)", "win.back");
+        let forward_button = navigation_button("go-next-symbolic", "Forward (Alt+Right)", "win.forward");
+        let up_button = navigation_button("go-up-symbolic", "Up (Alt+Up)", "win.up");
+        let refresh_button = navigation_button("view-refresh-symbolic", "Refresh (F5)", "win.refresh");
+
+        // Breadcrumbs of the current location, scrolls if the path gets too long
+        let path_bar = Box::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(2)
+            .build();
+
+        let path_scroll = ScrolledWindow::builder()
+            .hscrollbar_policy(PolicyType::External)
+            .vscrollbar_policy(PolicyType::Never)
+            .hexpand(true)
+            .child(&path_bar)
+            .build();
+
+        // Bookmarked folders, saved in the config file
+        let bookmarks_list = Box::new(Orientation::Vertical, 2);
+        let add_bookmark_button = Button::builder()
+            .label("Bookmark this folder")
+            .action_name("win.bookmark")
+            .build();
+        add_bookmark_button.add_css_class("flat");
+
+        let bookmarks_content = Box::new(Orientation::Vertical, 6);
+        bookmarks_content.append(&bookmarks_list);
+        bookmarks_content.append(&Separator::new(Orientation::Horizontal));
+        bookmarks_content.append(&add_bookmark_button);
+
+        let bookmarks_popover = Popover::builder()
+            .child(&bookmarks_content)
+            .build();
+
+        let bookmarks_button = MenuButton::builder()
+            .icon_name("user-bookmarks-symbolic")
+            .tooltip_text("Bookmarks")
+            .popover(&bookmarks_popover)
+            .build();
+
+        toolbar.append(&back_button);
+        toolbar.append(&forward_button);
+        toolbar.append(&up_button);
+        toolbar.append(&refresh_button);
+        toolbar.append(&path_scroll);
+        toolbar.append(&bookmarks_button);
+
+        // Editable location, accepts smb:// URLs as well as UNC paths
+        let location_entry = Entry::builder()
+            .placeholder_text("smb://server/share or \\\\server\\share")
+            .hexpand(true)
+            .build();
+
+        // ---- Loading indicator ----
+        let loading_bar = Box::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(6)
+            .halign(Align::Center)
+            .visible(false)
+            .build();
+
+        let spinner = Spinner::new();
+        let loading_label = Label::new(Some("Loading…"));
+        let cancel_button = Button::with_label("Cancel");
+
+        loading_bar.append(&spinner);
+        loading_bar.append(&loading_label);
+        loading_bar.append(&cancel_button);
+
+        let browser = Rc::new(Browser {
+            list_store,
+            reporter,
+            connection: RefCell::new(None),
+            generation: Cell::new(0),
+            printer_queues: RefCell::new(Vec::new()),
+            current: RefCell::new(None),
+            last_path: RefCell::new(None),
+            back_stack: RefCell::new(Vec::new()),
+            forward_stack: RefCell::new(Vec::new()),
+            printer_handler: RefCell::new(None),
+            login_handler: RefCell::new(None),
+            queues_handler: RefCell::new(None),
+            pending_login: RefCell::new(None),
+            toolbar,
+            path_bar,
+            location_entry,
+            loading_bar,
+            loading_label,
+            spinner,
+            bookmarks_popover,
+            bookmarks_list,
+            back_action: SimpleAction::new("back", None),
+            forward_action: SimpleAction::new("forward", None),
+            up_action: SimpleAction::new("up", None),
+            refresh_action: SimpleAction::new("refresh", None),
+            bookmark_action: SimpleAction::new("bookmark", None),
+        });
+
+        let weak = Rc::downgrade(&browser);
+        cancel_button.connect_clicked(move |_| {
+            if let Some(browser) = weak.upgrade() {
+                browser.cancel();
+            }
+        });
+
+        let weak = Rc::downgrade(&browser);
+        browser.location_entry.connect_activate(move |entry| {
+            if let Some(browser) = weak.upgrade() {
+                match parse_location(&entry.text()) {
+                    Ok(location) => browser.open_location(location),
+                    Err(e) => browser.reporter.error("This is not a valid SMB location.", &e.to_string()),
+                }
+            }
+        });
+
+        let weak = Rc::downgrade(&browser);
+        browser.back_action.connect_activate(move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                browser.go_back();
+            }
+        });
+
+        let weak = Rc::downgrade(&browser);
+        browser.forward_action.connect_activate(move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                browser.go_forward();
+            }
+        });
+
+        let weak = Rc::downgrade(&browser);
+        browser.up_action.connect_activate(move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                browser.go_up();
+            }
+        });
+
+        let weak = Rc::downgrade(&browser);
+        browser.refresh_action.connect_activate(move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                browser.refresh();
+            }
+        });
+
+        let weak = Rc::downgrade(&browser);
+        browser.bookmark_action.connect_activate(move |_, _| {
+            if let Some(browser) = weak.upgrade() {
+                browser.bookmark_current();
+            }
+        });
+
+        // The list is read from the config every time, it may have been changed in the meantime
+        let weak = Rc::downgrade(&browser);
+        browser.bookmarks_popover.connect_show(move |_| {
+            if let Some(browser) = weak.upgrade() {
+                browser.update_bookmarks();
+            }
+        });
+
+        browser.update_navigation();
+        browser
+    }
+
+    // Back, Forward, Up and Refresh buttons together with the breadcrumbs and the bookmarks
+    pub fn toolbar(&self) -> &Box {
+        &self.toolbar
+    }
+
+    // Entry for typing a smb:// URL or UNC path
+    pub fn location_entry(&self) -> &Entry {
+        &self.location_entry
+    }
+
+    // The loading indicator, to be placed above the list
+    pub fn loading_bar(&self) -> &Box {
+        &self.loading_bar
+    }
+
+    // Install the navigation actions as win.back, win.forward, win.up, win.refresh and win.bookmark
+    pub fn add_actions<M: IsA<ActionMap>>(&self, map: &M) {
+        map.add_action(&self.back_action);
+        map.add_action(&self.forward_action);
+        map.add_action(&self.up_action);
+        map.add_action(&self.refresh_action);
+        map.add_action(&self.bookmark_action);
+    }
+
+    pub fn reporter(&self) -> &Rc<ErrorReporter> {
+        &self.reporter
+    }
+
+    pub fn connection(&self) -> Option<Rc<SambaWorker>> {
+        self.connection.borrow().clone()
+    }
+
+    pub fn set_connection(self: &Rc<Self>, connection: SambaWorker) {
+        // Results of the old connection must not end up in the list anymore
+        self.stop_loading();
+        self.save_last_path();
+        *self.connection.borrow_mut() = Some(Rc::new(connection));
+        self.pending_login.replace(None);
+
+        // The history belongs to the old connection
+        self.current.replace(None);
+        self.back_stack.borrow_mut().clear();
+        self.forward_stack.borrow_mut().clear();
+        self.update_navigation();
+    }
+
+    pub fn printer_queues(&self) -> Vec<Rc<PrinterQueue>> {
+        self.printer_queues.borrow().clone()
+    }
+
+    // Remember the queues of the CUPS server and mark the listed printers that are installed
+    pub fn set_printer_queues(&self, queues: Vec<Rc<PrinterQueue>>) {
+        self.printer_queues.replace(queues);
+
+        for entry in self.list_store.iter::<SambaEntryObject>().flatten() {
+            self.mark_installed(&entry);
+        }
+
+        // The rows only show the new state once they are bound again
+        let n_items = self.list_store.n_items();
+        self.list_store.items_changed(0, n_items, n_items);
+
+        if let Some(handler) = self.queues_handler.borrow().as_ref() {
+            handler(&self.printer_queues.borrow());
+        }
+    }
+
+    pub fn set_printer_handler<F: Fn(SambaEntryObject) + 'static>(&self, handler: F) {
+        self.printer_handler.replace(Some(std::boxed::Box::new(handler)));
+    }
+
+    pub fn set_login_handler<F: Fn(Option<SambaLocation>) + 'static>(&self, handler: F) {
+        self.login_handler.replace(Some(std::boxed::Box::new(handler)));
+    }
+
+    pub fn set_queues_handler<F: Fn(&[Rc<PrinterQueue>]) + 'static>(&self, handler: F) {
+        self.queues_handler.replace(Some(std::boxed::Box::new(handler)));
+    }
+
+    // Run `action` after the next listing that succeeds, it is dropped if the server refuses the login
+    pub fn set_pending_login<F: FnOnce() + 'static>(&self, action: F) {
+        self.pending_login.replace(Some(std::boxed::Box::new(action)));
+    }
+
+    // Open an entry of the list: browse into folders, hand printers to the printer handler
+    pub fn activate(self: &Rc<Self>, entry: &SambaEntryObject) {
+        let server = match entry.server_path() {
+            Some(server) => server,
+            None => {
+                self.reporter.error("This entry can't be opened.", &format!("No server path found for {}", entry.name()));
+                return;
+            }
+        };
+
+        match entry.entry_type() {
+            SambaEntryType::Workgroup | SambaEntryType::Server | SambaEntryType::Directory => {
+                self.navigate(server);
+            }
+            SambaEntryType::Printer => {
+                if let Some(handler) = self.printer_handler.borrow().as_ref() {
+                    handler(entry.clone());
+                }
+            }
+            // Files and unknown types are not interactable in this version, so we do nothing on click
+            // In the future we might want to add functionality for files (e.g. open with default app, download, etc.), but there is no plan for that.
+            _ => {}
+        }
+    }
+
+    // Jump to a location typed by the user.
+    // Without a connection, or if the location names another user, the login handler is asked first.
+    pub fn open_location(self: &Rc<Self>, location: SambaLocation) {
+        let needs_login = match self.connection() {
+            Some(worker) => location.username.as_ref().is_some_and(|u| *u != worker.credentials.username),
+            None => true,
+        };
+
+        if needs_login {
+            if let Some(handler) = self.login_handler.borrow().as_ref() {
+                handler(Some(location));
+            }
+            return;
+        }
+
+        self.open_url(location.url);
+    }
+
+    // Open `url` with the current connection. The parent is listed first to find out
+    // if the target is a printer share, which opens the printer handler instead of browsing.
+    pub fn open_url(self: &Rc<Self>, url: Url) {
+        let (worker, parent, name) = match (self.connection(), parent_url(&url), url.path_segments().and_then(|mut s| s.rfind(|s| !s.is_empty()))) {
+            (Some(worker), Some(parent), Some(name)) => (worker, parent, url_escape::decode(name).to_string()),
+            _ => {
+                self.navigate(url);
+                return;
+            }
+        };
+
+        let generation = self.generation.get() + 1;
+        self.generation.set(generation);
+        self.set_loading(Some(&url));
+
+        let browser = self.clone();
+        MainContext::default().spawn_local(async move {
+            let entries = worker.list_directory(&parent).await;
+
+            if browser.generation.get() != generation {
+                return;
+            }
+            browser.set_loading(None);
+            browser.login_result(entries.as_ref().err());
+
+            let entry = entries.ok().and_then(|entries| {
+                entries.into_iter().find(|e| e.name.eq_ignore_ascii_case(&name))
+            });
+
+            match entry {
+                Some(entry) => {
+                    if let Some(entry_url) = entry.url(&parent) {
+                        browser.activate(&SambaEntryObject::new(&entry, &entry_url));
+                    }
+                }
+                // Not visible in the parent (e.g. hidden share), just try to browse it
+                None => {
+                    let mut url = url;
+                    if !url.path().ends_with('/') {
+                        url.set_path(&format!("{}/", url.path()));
+                    }
+                    browser.navigate(url);
+                }
+            }
+        });
+    }
+
+    // Navigate to `url` and remember the current location in the history
+    pub fn navigate(self: &Rc<Self>, url: Url) {
+        let previous = self.current.replace(Some(url.clone()));
+        if let Some(previous) = previous {
+            if previous != url {
+                self.back_stack.borrow_mut().push(previous);
+                self.forward_stack.borrow_mut().clear();
+            }
+        }
+
+        self.load(url);
+    }
+
+    pub fn go_back(self: &Rc<Self>) {
+        let target = self.back_stack.borrow_mut().pop();
+        if let Some(target) = target {
+            if let Some(current) = self.current.replace(Some(target.clone())) {
+                self.forward_stack.borrow_mut().push(current);
+            }
+            self.load(target);
+        }
+    }
+
+    pub fn go_forward(self: &Rc<Self>) {
+        let target = self.forward_stack.borrow_mut().pop();
+        if let Some(target) = target {
+            if let Some(current) = self.current.replace(Some(target.clone())) {
+                self.back_stack.borrow_mut().push(current);
+            }
+            self.load(target);
+        }
+    }
+
+    pub fn go_up(self: &Rc<Self>) {
+        let parent = self.current.borrow().as_ref().and_then(parent_url);
+        if let Some(parent) = parent {
+            self.navigate(parent);
+        }
+    }
+
+    pub fn refresh(self: &Rc<Self>) {
+        let current = self.current.borrow().clone();
+        if let Some(current) = current {
+            self.load(current);
+        }
+    }
+
+    // Cancel the current listing. A listing that hangs on an unresponsive server would block
+    // every later request of the connection, so the worker gives up on it as well.
+    pub fn cancel(&self) {
+        if self.loading_bar.is_visible() {
+            if let Some(connection) = self.connection() {
+                if let Err(e) = connection.cancel() {
+                    self.reporter.error("The SMB connection could not be restarted.", &e.to_string());
+                }
+            }
+        }
+
+        self.stop_loading();
+    }
+
+    // Stop waiting for the current listing, its result will be ignored
+    fn stop_loading(&self) {
+        self.generation.set(self.generation.get() + 1);
+        self.set_loading(None);
+    }
+
+    // List the directory at `url` in the background and show its entries once they arrive
+    fn load(self: &Rc<Self>, url: Url) {
+        self.update_navigation();
+
+        let worker = match self.connection() {
+            Some(worker) => worker,
+            None => return,
+        };
+
+        let generation = self.generation.get() + 1;
+        self.generation.set(generation);
+        self.set_loading(Some(&url));
+
+        let browser = self.clone();
+        MainContext::default().spawn_local(async move {
+            let result = worker.list_directory(&url).await;
+
+            // The user navigated somewhere else or cancelled, drop the result
+            if browser.generation.get() != generation {
+                return;
+            }
+            browser.set_loading(None);
+            browser.login_result(result.as_ref().err());
+
+            match result {
+                Ok(entries) => {
+                    browser.remember_last_path(&url);
+                    browser.list_store.remove_all();
+                    for entry in entries {
+                        if let Some(server_path) = entry.url(&url) {
+                            let entry = SambaEntryObject::new(&entry, &server_path);
+                            browser.mark_installed(&entry);
+                            browser.list_store.append(&entry);
+                        }
+                    }
+                }
+                Err(e) => browser.reporter.error(&format!("Could not open {}.\n{}", url, e), &format!("{:?}", e)),
+            }
+        });
+    }
+
+    // Remember `url` as last visited folder of its server, so the next login continues there.
+    // Only kept in memory here, the folder of another server is written right away.
+    fn remember_last_path(&self, url: &Url) {
+        let previous = self.last_path.replace(Some(url.clone()));
+        if let Some(previous) = previous.filter(|previous| previous.host_str() != url.host_str()) {
+            write_last_path(&previous);
+        }
+    }
+
+    // Write the last visited folder to the config, when the connection changes or the window closes
+    pub fn save_last_path(&self) {
+        let last_path = self.last_path.take();
+        if let Some(url) = last_path {
+            write_last_path(&url);
+        }
+    }
+
+    // The first listing that works confirms the login, a refused login drops what waits for it.
+    // Other errors (e.g. a folder that is gone) say nothing about the login.
+    fn login_result(&self, error: Option<&SambaError>) {
+        match error {
+            None => {
+                let pending = self.pending_login.take();
+                if let Some(pending) = pending {
+                    pending();
+                }
+            }
+            Some(SambaError::LogonFailure) => {
+                self.pending_login.replace(None);
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn mark_installed(&self, entry: &SambaEntryObject) {
+        if entry.entry_type() != SambaEntryType::Printer {
+            return;
+        }
+
+        let installed_as = match entry.server_path() {
+            Some(url) => self.printer_queues.borrow().iter()
+                .filter(|queue| queue.is_for_share(&url))
+                .map(|queue| queue.name.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        entry.set_installed_as(installed_as);
+    }
+
+    fn set_loading(&self, url: Option<&Url>) {
+        match url {
+            Some(url) => {
+                self.loading_label.set_text(&format!("Loading {}…", url));
+                self.spinner.start();
+                self.loading_bar.set_visible(true);
+            }
+            None => {
+                self.spinner.stop();
+                self.loading_bar.set_visible(false);
+            }
+        }
+    }
+
+    // Add the current folder to the bookmarks
+    fn bookmark_current(&self) {
+        let current = match self.current.borrow().clone() {
+            Some(current) => current,
+            None => return,
+        };
+
+        let config = Config::load();
+        let mut bookmarks = config.bookmarks();
+        if bookmarks.contains(&current) {
+            return;
+        }
+
+        bookmarks.push(current.clone());
+        config.set_bookmarks(&bookmarks);
+        match config.save() {
+            Ok(()) => self.reporter.info(&format!("Bookmarked {}.", bookmark_label(&current))),
+            Err(e) => self.reporter.warning("The bookmark could not be saved.", &e.to_string()),
+        }
+    }
+
+    fn remove_bookmark(self: &Rc<Self>, url: &Url) {
+        let config = Config::load();
+        let bookmarks: Vec<Url> = config.bookmarks().into_iter().filter(|b| b != url).collect();
+        config.set_bookmarks(&bookmarks);
+        if let Err(e) = config.save() {
+            self.reporter.warning("The bookmark could not be removed.", &e.to_string());
+        }
+
+        self.update_bookmarks();
+    }
+
+    // Rebuild the bookmark list of the popover, a click opens the folder (or asks for a login first)
+    fn update_bookmarks(self: &Rc<Self>) {
+        while let Some(child) = self.bookmarks_list.first_child() {
+            self.bookmarks_list.remove(&child);
+        }
+
+        let bookmarks = Config::load().bookmarks();
+        if bookmarks.is_empty() {
+            let empty = Label::new(Some("No bookmarks yet"));
+            empty.add_css_class("dim-label");
+            self.bookmarks_list.append(&empty);
+        }
+
+        for url in bookmarks {
+            let row = Box::new(Orientation::Horizontal, 2);
+
+            let open_button = Button::builder()
+                .label(bookmark_label(&url))
+                .hexpand(true)
+                .build();
+            open_button.add_css_class("flat");
+
+            let remove_button = Button::from_icon_name("edit-delete-symbolic");
+            remove_button.set_tooltip_text(Some("Remove bookmark"));
+            remove_button.add_css_class("flat");
+
+            let weak = Rc::downgrade(self);
+            let open_url = url.clone();
+            open_button.connect_clicked(move |_| {
+                if let Some(browser) = weak.upgrade() {
+                    browser.bookmarks_popover.popdown();
+                    match parse_location(open_url.as_str()) {
+                        Ok(location) => browser.open_location(location),
+                        Err(e) => browser.reporter.error("The bookmark is not a valid SMB location.", &e.to_string()),
+                    }
+                }
+            });
+
+            let weak = Rc::downgrade(self);
+            remove_button.connect_clicked(move |_| {
+                if let Some(browser) = weak.upgrade() {
+                    browser.remove_bookmark(&url);
+                }
+            });
+
+            row.append(&open_button);
+            row.append(&remove_button);
+            self.bookmarks_list.append(&row);
+        }
+    }
+
+    // Update the enabled state of the actions and rebuild the breadcrumbs
+    fn update_navigation(self: &Rc<Self>) {
+        let current = self.current.borrow().clone();
+
+        self.back_action.set_enabled(!self.back_stack.borrow().is_empty());
+        self.forward_action.set_enabled(!self.forward_stack.borrow().is_empty());
+        self.up_action.set_enabled(current.as_ref().and_then(parent_url).is_some());
+        self.refresh_action.set_enabled(current.is_some());
+        self.bookmark_action.set_enabled(current.is_some());
+
+        if let Some(current) = current.as_ref() {
+            self.location_entry.set_text(current.as_str());
+        }
+
+        while let Some(child) = self.path_bar.first_child() {
+            self.path_bar.remove(&child);
+        }
+
+        if let Some(current) = current {
+            for (i, (name, url)) in breadcrumbs(&current).into_iter().enumerate() {
+                if i > 0 {
+                    self.path_bar.append(&Label::new(Some("›")));
+                }
+
+                let button = Button::with_label(&name);
+                button.add_css_class("flat");
+
+                let weak = Rc::downgrade(self);
+                button.connect_clicked(move |_| {
+                    if let Some(browser) = weak.upgrade() {
+                        browser.navigate(url.clone());
+                    }
+                });
+
+                self.path_bar.append(&button);
+            }
+        }
+    }
+}
+
+fn write_last_path(url: &Url) {
+    if let Some(host) = url.host_str().filter(|host| !host.is_empty()) {
+        let config = Config::load();
+        config.set_last_path(host, url);
+        // Not worth an error message, the worst case is starting at the root of the server
+        let _ = config.save();
+    }
+}
+
+// "server/share/folder/" of a bookmark, readable without percent-encoding
+fn bookmark_label(url: &Url) -> String {
+    url_escape::decode(&url[Position::BeforeHost..]).to_string()
+}
+
+fn navigation_button(icon: &str, tooltip: &str, action: &str) -> Button {
+    let button = Button::from_icon_name(icon);
+    button.set_tooltip_text(Some(tooltip));
+    button.set_action_name(Some(action));
+    button
+}
+
+// Split a smb:// URL into (label, URL) pairs for each level, starting at the network root
+fn breadcrumbs(url: &Url) -> Vec<(String, Url)> {
+    let mut crumbs = Vec::new();
+
+    if let Ok(root) = Url::parse("smb://") {
+        crumbs.push(("smb://".to_string(), root));
+    }
+
+    let host = match url.host_str() {
+        Some(host) if !host.is_empty() => host,
+        _ => return crumbs,
+    };
+
+    let mut level = url.clone();
+    level.set_path("/");
+    crumbs.push((url_escape::decode(host).to_string(), level.clone()));
+
+    if let Some(segments) = url.path_segments() {
+        let mut path = String::from("/");
+        for segment in segments.filter(|s| !s.is_empty()) {
+            path.push_str(segment);
+            path.push('/');
+
+            let mut level = level.clone();
+            level.set_path(&path);
+            crumbs.push((url_escape::decode(segment).to_string(), level));
+        }
+    }
+
+    crumbs
+}
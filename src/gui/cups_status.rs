@@ -0,0 +1,173 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+
+use glib::MainContext;
+use gtk::{prelude::*, Align, Box, Button, Image, Label, Orientation, Spinner};
+use crate::config::Config;
+use crate::cups::CupsManager;
+use crate::gui::error_reporter::ErrorReporter;
+
+// Lazy and fallible access to CUPS.
+// Browsing SMB works without CUPS, so the connection is only made when it is needed.
+// Connecting runs in the background and can be cancelled, a hung cupsd never blocks the window.
+// While CUPS can't be reached a status bar with a Retry button is shown.
+pub(crate) struct CupsService {
+    manager: RefCell<Option<Arc<CupsManager>>>,
+    // Cancel flag of the running connection attempt
+    connecting: RefCell<Option<Arc<AtomicI32>>>,
+    // Callers waiting for the running attempt, they get its result as well
+    waiting: RefCell<Vec<oneshot::Sender<Option<Arc<CupsManager>>>>>,
+    reporter: Rc<ErrorReporter>,
+    status_bar: Box,
+    status_icon: Image,
+    spinner: Spinner,
+    status_label: Label,
+    retry_button: Button,
+    cancel_button: Button,
+}
+
+impl CupsService {
+    pub fn new(reporter: Rc<ErrorReporter>) -> Rc<Self> {
+        let status_bar = Box::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(6)
+            .visible(false)
+            .build();
+
+        let status_icon = Image::from_icon_name("printer-error-symbolic");
+        let spinner = Spinner::new();
+        let status_label = Label::builder()
+            .label("CUPS not reachable. Printers can't be installed.")
+            .xalign(0.0)
+            .hexpand(true)
+            .wrap(true)
+            .build();
+        status_label.add_css_class("warning");
+
+        let retry_button = Button::builder()
+            .label("Retry")
+            .valign(Align::Center)
+            .build();
+
+        let cancel_button = Button::builder()
+            .label("Cancel")
+            .valign(Align::Center)
+            .build();
+
+        status_bar.append(&status_icon);
+        status_bar.append(&spinner);
+        status_bar.append(&status_label);
+        status_bar.append(&retry_button);
+        status_bar.append(&cancel_button);
+
+        let service = Rc::new(CupsService {
+            manager: RefCell::new(None),
+            connecting: RefCell::new(None),
+            waiting: RefCell::new(Vec::new()),
+            reporter,
+            status_bar,
+            status_icon,
+            spinner,
+            status_label,
+            retry_button: retry_button.clone(),
+            cancel_button: cancel_button.clone(),
+        });
+
+        let weak = Rc::downgrade(&service);
+        retry_button.connect_clicked(move |_| {
+            if let Some(service) = weak.upgrade() {
+                MainContext::default().spawn_local(async move {
+                    if service.manager().await.is_some() {
+                        service.reporter.info("Connected to CUPS.");
+                    }
+                });
+            }
+        });
+
+        let weak = Rc::downgrade(&service);
+        cancel_button.connect_clicked(move |_| {
+            if let Some(service) = weak.upgrade() {
+                service.cancel();
+            }
+        });
+
+        service
+    }
+
+    // The "CUPS not reachable" state, to be placed in the main window
+    pub fn status_bar(&self) -> &Box {
+        &self.status_bar
+    }
+
+    // The connected manager, connects to CUPS on first use and after a failed attempt.
+    // A caller that comes while an attempt is running waits for it.
+    // None if CUPS can't be reached or the attempt was cancelled.
+    pub async fn manager(&self) -> Option<Arc<CupsManager>> {
+        if let Some(manager) = self.manager.borrow().as_ref() {
+            return Some(manager.clone());
+        }
+
+        if self.connecting.borrow().is_some() {
+            let (tx, rx) = oneshot::channel();
+            self.waiting.borrow_mut().push(tx);
+            return rx.await.unwrap_or(None);
+        }
+
+        let cancel = Arc::new(AtomicI32::new(0));
+        self.connecting.replace(Some(cancel.clone()));
+        self.show_status("Connecting to CUPS…", true);
+
+        let timeout_ms = Config::load().cups_timeout_ms();
+        let result = CupsManager::connect_in_background(timeout_ms, cancel.clone()).await;
+
+        // Cancelled in the meantime, the UI already moved on
+        if cancel.load(Ordering::Relaxed) != 0 {
+            return None;
+        }
+        self.connecting.replace(None);
+
+        let manager = match result {
+            Ok(manager) => {
+                let manager = Arc::new(manager);
+                self.manager.replace(Some(manager.clone()));
+                self.status_bar.set_visible(false);
+                Some(manager)
+            }
+            Err(e) => {
+                self.show_status(&format!("CUPS not reachable. Printers can't be installed.\n{}", e.message), false);
+                None
+            }
+        };
+
+        self.notify_waiting(&manager);
+        manager
+    }
+
+    // Stop waiting for the running connection attempt
+    fn cancel(&self) {
+        if let Some(cancel) = self.connecting.replace(None) {
+            cancel.store(1, Ordering::Relaxed);
+            self.show_status("Connecting to CUPS was cancelled. Printers can't be installed.", false);
+            self.notify_waiting(&None);
+        }
+    }
+
+    fn notify_waiting(&self, manager: &Option<Arc<CupsManager>>) {
+        for waiting in self.waiting.take() {
+            let _ = waiting.send(manager.clone());
+        }
+    }
+
+    // While connecting a spinner and Cancel are shown, afterwards the error and Retry
+    fn show_status(&self, message: &str, connecting: bool) {
+        self.status_label.set_text(message);
+        self.status_icon.set_visible(!connecting);
+        self.spinner.set_visible(connecting);
+        self.spinner.set_spinning(connecting);
+        self.retry_button.set_visible(!connecting);
+        self.cancel_button.set_visible(connecting);
+        self.status_bar.set_visible(true);
+    }
+}
@@ -0,0 +1,237 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk::{prelude::*, Align, Box, Button, Expander, Image, Label, Orientation, Revealer, RevealerTransitionType, Window};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Severity {
+    // Confirmation of a finished action, hides itself after a few seconds
+    Info,
+    Warning,
+    Error,
+    // Shown as modal dialog, the current action can't continue
+    Fatal,
+}
+
+// Central place to show errors to the user.
+// Info, warnings and errors are shown as banner on top of the main window,
+// fatal errors as modal dialog. The technical details are hidden in an expander.
+pub(crate) struct ErrorReporter {
+    banner: Revealer,
+    icon: Image,
+    message: Label,
+    details_expander: Expander,
+    details: Label,
+    window: RefCell<Option<Window>>,
+    // Used to only hide the banner of the latest info message
+    generation: Cell<u64>,
+}
+
+impl ErrorReporter {
+    pub fn new() -> Rc<Self> {
+        let content = Box::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(6)
+            .margin_top(6)
+            .margin_bottom(6)
+            .margin_start(6)
+            .margin_end(6)
+            .build();
+        content.add_css_class("card");
+
+        let icon = Image::new();
+        icon.set_valign(Align::Start);
+
+        let text = Box::new(Orientation::Vertical, 4);
+        text.set_hexpand(true);
+
+        let message = Label::builder()
+            .xalign(0.0)
+            .wrap(true)
+            .build();
+
+        let details = Label::builder()
+            .xalign(0.0)
+            .wrap(true)
+            .selectable(true)
+            .build();
+        details.add_css_class("monospace");
+
+        let details_expander = Expander::builder()
+            .label("Details")
+            .child(&details)
+            .build();
+
+        text.append(&message);
+        text.append(&details_expander);
+
+        let close_button = Button::from_icon_name("window-close-symbolic");
+        close_button.add_css_class("flat");
+        close_button.set_valign(Align::Start);
+
+        content.append(&icon);
+        content.append(&text);
+        content.append(&close_button);
+
+        let banner = Revealer::builder()
+            .transition_type(RevealerTransitionType::SlideDown)
+            .child(&content)
+            .build();
+
+        close_button.connect_clicked({
+            let banner = banner.clone();
+            move |_| banner.set_reveal_child(false)
+        });
+
+        Rc::new(ErrorReporter {
+            banner,
+            icon,
+            message,
+            details_expander,
+            details,
+            window: RefCell::new(None),
+            generation: Cell::new(0),
+        })
+    }
+
+    // The banner, to be placed on top of the main window
+    pub fn banner(&self) -> &Revealer {
+        &self.banner
+    }
+
+    // Parent window for fatal error dialogs
+    pub fn set_window<W: IsA<Window>>(&self, window: &W) {
+        self.window.replace(Some(window.clone().upcast()));
+    }
+
+    pub fn info(self: &Rc<Self>, message: &str) {
+        self.report(Severity::Info, message, "");
+    }
+
+    pub fn warning(self: &Rc<Self>, message: &str, details: &str) {
+        self.report(Severity::Warning, message, details);
+    }
+
+    pub fn error(self: &Rc<Self>, message: &str, details: &str) {
+        self.report(Severity::Error, message, details);
+    }
+
+    pub fn fatal(self: &Rc<Self>, message: &str, details: &str) {
+        self.report(Severity::Fatal, message, details);
+    }
+
+    pub fn report(self: &Rc<Self>, severity: Severity, message: &str, details: &str) {
+        // Debug builds also log to the terminal, releases only use the banner and dialogs
+        if cfg!(debug_assertions) && severity != Severity::Info {
+            eprintln!("{:?}: {} {}", severity, message, details);
+        }
+
+        let generation = self.generation.get() + 1;
+        self.generation.set(generation);
+
+        if severity == Severity::Fatal {
+            self.banner.set_reveal_child(false);
+            self.show_dialog(message, details);
+            return;
+        }
+
+        let (icon, css_class) = match severity {
+            Severity::Info => ("emblem-ok-symbolic", "success"),
+            Severity::Warning => ("dialog-warning-symbolic", "warning"),
+            _ => ("dialog-error-symbolic", "error"),
+        };
+
+        self.icon.set_icon_name(Some(icon));
+        for class in ["success", "warning", "error"] {
+            self.icon.remove_css_class(class);
+        }
+        self.icon.add_css_class(css_class);
+
+        self.message.set_text(message);
+        self.details.set_text(details);
+        self.details_expander.set_expanded(false);
+        self.details_expander.set_visible(!details.is_empty());
+        self.banner.set_reveal_child(true);
+
+        // Info messages behave like a toast and disappear on their own
+        if severity == Severity::Info {
+            let weak = Rc::downgrade(self);
+            glib::timeout_add_local_once(Duration::from_secs(5), move || {
+                if let Some(reporter) = weak.upgrade() {
+                    if reporter.generation.get() == generation {
+                        reporter.banner.set_reveal_child(false);
+                    }
+                }
+            });
+        }
+    }
+
+    fn show_dialog(&self, message: &str, details: &str) {
+        let dialog = Window::builder()
+            .title("Error")
+            .modal(true)
+            .default_width(420)
+            .resizable(false)
+            .build();
+
+        if let Some(parent) = self.window.borrow().as_ref() {
+            dialog.set_transient_for(Some(parent));
+        }
+
+        let vbox = Box::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(12)
+            .margin_top(12)
+            .margin_bottom(12)
+            .margin_start(12)
+            .margin_end(12)
+            .build();
+
+        let header = Box::new(Orientation::Horizontal, 12);
+        let icon = Image::from_icon_name("dialog-error-symbolic");
+        icon.set_pixel_size(32);
+        icon.add_css_class("error");
+
+        let message_label = Label::builder()
+            .label(message)
+            .xalign(0.0)
+            .wrap(true)
+            .hexpand(true)
+            .build();
+
+        header.append(&icon);
+        header.append(&message_label);
+        vbox.append(&header);
+
+        if !details.is_empty() {
+            let details_label = Label::builder()
+                .label(details)
+                .xalign(0.0)
+                .wrap(true)
+                .selectable(true)
+                .build();
+            details_label.add_css_class("monospace");
+
+            let expander = Expander::builder()
+                .label("Technical details")
+                .child(&details_label)
+                .build();
+            vbox.append(&expander);
+        }
+
+        let close = Button::builder()
+            .label("Close")
+            .halign(Align::End)
+            .build();
+
+        close.connect_clicked({
+            let dialog = dialog.clone();
+            move |_| dialog.close()
+        });
+
+        vbox.append(&close);
+        dialog.set_child(Some(&vbox));
+        dialog.set_visible(true);
+    }
+}
@@ -0,0 +1,191 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use glib::subclass::prelude::ObjectSubclassIsExt;
+use glib::Object;
+use gtk::gio::{ListModel, ListStore};
+use gtk::{prelude::*, Align, Box, Button, ColumnView, ColumnViewColumn, Label, ListItem, NoSelection, Orientation, ScrolledWindow, SignalListItemFactory};
+use crate::cups::PrinterQueue;
+
+mod printer_queue_object {
+    use glib::subclass::prelude::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::cups::PrinterQueue;
+
+    #[derive(Default)]
+    pub struct PrinterQueueObject {
+        pub queue: RefCell<Rc<PrinterQueue>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for PrinterQueueObject {
+        const NAME: &'static str = "PrinterQueueObject";
+        type Type = super::PrinterQueueObject;
+    }
+
+    impl ObjectImpl for PrinterQueueObject {}
+}
+
+glib::wrapper! {
+    pub struct PrinterQueueObject(ObjectSubclass<printer_queue_object::PrinterQueueObject>);
+}
+
+impl PrinterQueueObject {
+    pub fn new(queue: Rc<PrinterQueue>) -> Self {
+        let obj: Self = Object::new();
+        obj.imp().queue.replace(queue);
+        obj
+    }
+
+    pub fn queue(&self) -> Rc<PrinterQueue> {
+        self.imp().queue.borrow().clone()
+    }
+
+    fn imp(&self) -> &printer_queue_object::PrinterQueueObject {
+        ObjectSubclassIsExt::imp(self)
+    }
+}
+
+// Second page of the window: every CUPS queue that prints to a SMB share, with its state.
+// The list is filled by set_queues, the refresh button only asks for that through the refresh handler.
+pub(crate) struct InstalledPrinters {
+    widget: Box,
+    list_store: ListStore,
+    scrolled: ScrolledWindow,
+    empty_label: Label,
+    refresh_button: Button,
+    loading: Cell<bool>,
+}
+
+impl InstalledPrinters {
+    pub fn new() -> Rc<Self> {
+        let list_store = ListStore::new::<PrinterQueueObject>();
+        let selection = NoSelection::new(Some(list_store.clone().upcast::<ListModel>()));
+
+        let column_view = ColumnView::builder()
+            .model(&selection)
+            .show_column_separators(true)
+            .show_row_separators(true)
+            .build();
+
+        column_view.append_column(&text_column("Printer", |queue| queue.name.clone()));
+        column_view.append_column(&text_column("State", |queue| queue.state.label().to_string()));
+        column_view.append_column(&text_column("Reasons", |queue| queue.state_reasons.join(", ")));
+        column_view.append_column(&text_column("Accepting jobs", |queue| {
+            if queue.accepting_jobs { "Yes" } else { "No" }.to_string()
+        }));
+        column_view.append_column(&text_column("Location", |queue| queue.location.clone()));
+        column_view.append_column(&text_column("Driver", |queue| queue.make_and_model.clone()));
+        column_view.append_column(&text_column("Share", |queue| match &queue.share {
+            Some(share) if share.workgroup.is_empty() => format!("\\\\{}\\{}", share.server, share.share),
+            Some(share) => format!("\\\\{}\\{} ({})", share.server, share.share, share.workgroup),
+            None => "Unrecognized smb:// device URI".to_string(),
+        }));
+
+        let scrolled = ScrolledWindow::builder()
+            .child(&column_view)
+            .vexpand(true)
+            .build();
+
+        let empty_label = Label::builder()
+            .label("No printers with a SMB share are installed in CUPS.")
+            .vexpand(true)
+            .valign(Align::Center)
+            .build();
+        empty_label.add_css_class("dim-label");
+
+        let refresh_button = Button::builder()
+            .icon_name("view-refresh-symbolic")
+            .tooltip_text("Reload the printers from CUPS")
+            .halign(Align::End)
+            .build();
+
+        let widget = Box::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(6)
+            .build();
+
+        widget.append(&refresh_button);
+        widget.append(&empty_label);
+        widget.append(&scrolled);
+
+        let this = Rc::new(InstalledPrinters { widget, list_store, scrolled, empty_label, refresh_button, loading: Cell::new(false) });
+        this.update_empty();
+        this
+    }
+
+    pub fn widget(&self) -> &Box {
+        &self.widget
+    }
+
+    pub fn set_refresh_handler<F: Fn() + 'static>(&self, handler: F) {
+        self.refresh_button.connect_clicked(move |_| handler());
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.get()
+    }
+
+    // While the queues are loaded the refresh button is disabled
+    pub fn set_loading(&self, loading: bool) {
+        self.loading.set(loading);
+        self.refresh_button.set_sensitive(!loading);
+    }
+
+    // Show the queues that print to a SMB share, the others are left out.
+    // Queues whose smb:// device-uri can't be read are shown as well, without a share.
+    pub fn set_queues(&self, queues: &[Rc<PrinterQueue>]) {
+        self.list_store.remove_all();
+        for queue in queues.iter().filter(|queue| queue.smb_backend) {
+            self.list_store.append(&PrinterQueueObject::new(queue.clone()));
+        }
+        self.update_empty();
+    }
+
+    fn update_empty(&self) {
+        let empty = self.list_store.n_items() == 0;
+        self.empty_label.set_visible(empty);
+        self.scrolled.set_visible(!empty);
+    }
+}
+
+// Column that shows one text of each queue
+fn text_column(title: &str, text: fn(&PrinterQueue) -> String) -> ColumnViewColumn {
+    let factory = SignalListItemFactory::new();
+
+    factory.connect_setup(|_, obj| {
+        let list_item = obj
+            .downcast_ref::<ListItem>()
+            .expect("Needs to be a ListItem");
+
+        let label = Label::new(None);
+        label.set_xalign(0.0);
+        list_item.set_child(Some(&label));
+    });
+
+    factory.connect_bind(move |_, obj| {
+        let list_item = obj
+            .downcast_ref::<ListItem>()
+            .expect("Needs to be a ListItem");
+
+        let queue = list_item
+            .item()
+            .and_downcast::<PrinterQueueObject>()
+            .expect("Item should be PrinterQueueObject")
+            .queue();
+
+        let label = list_item
+            .child()
+            .and_downcast::<Label>()
+            .expect("Child should be Label");
+
+        label.set_text(&text(&queue));
+    });
+
+    ColumnViewColumn::builder()
+        .title(title)
+        .factory(&factory)
+        .resizable(true)
+        .build()
+}
@@ -1,135 +1,109 @@
+mod browser;
+mod cups_status;
+mod error_reporter;
+mod installed_printers;
 mod samba_entry_object;
 mod smb_login_dialog;
 pub mod printer_setup_dialog;
 
 use std::cell::RefCell;
+use std::path::Path;
 use std::rc::Rc;
+use std::sync::Arc;
+use crate::gui::browser::Browser;
+use crate::gui::cups_status::CupsService;
+use crate::gui::error_reporter::ErrorReporter;
+use crate::gui::installed_printers::InstalledPrinters;
 use crate::gui::samba_entry_object::SambaEntryObject;
-use crate::smb::{SambaConnection, SambaEntryType};
+use crate::smb::{read_credentials_file, SambaAuthMode, SambaEntryType};
 use glib::{clone, MainContext, Propagation};
 use gtk::gio::{ListModel, ListStore};
-use gtk::{prelude::*, Align, Application, ApplicationWindow, Box, Button, GestureClick, Label, ListItem, ListView, NoSelection, Orientation, ScrolledWindow, SignalListItemFactory};
-use crate::cups::{CupsManager, PpdInfo};
-use crate::gui::printer_setup_dialog::show_printer_setup_dialog;
+use gtk::{prelude::*, Align, Application, ApplicationWindow, Box, Button, GestureClick, Label, ListItem, ListView, NoSelection, Orientation, ScrolledWindow, SignalListItemFactory, Stack, StackSwitcher};
+use crate::config::Config;
+use crate::cups::{suggest_queue_name, CupsManager, PrinterAuthMode};
+use crate::gui::printer_setup_dialog::{show_printer_setup_dialog, PrinterSetupDefaults};
 
 
-pub fn build_ui(application: &Application) {
+// Build the main window. A credentials file given on the command line pre-fills the first login.
+pub fn build_ui(application: &Application, credentials_file: Option<&Path>) {
     // ---- State and Factories ----
     let factory = SignalListItemFactory::new();
-    let smb_state: Rc<RefCell<Option<Rc<SambaConnection>>>> = Rc::new(RefCell::new(None)); // Might look ugly, but it works need some refactoring in a later release
-    let cups_manager = CupsManager::new();
-
     let list_store = ListStore::new::<SambaEntryObject>();
+    let reporter = ErrorReporter::new();
+    let cups_service = CupsService::new(reporter.clone());
+    let browser = Browser::new(list_store.clone(), reporter.clone());
+    let installed_printers = InstalledPrinters::new();
     let no_selection = NoSelection::new(Some(list_store.clone().upcast::<ListModel>()));
     let app_window_holder: Rc<RefCell<Option<ApplicationWindow>>> = Rc::new(RefCell::new(None));
 
 
-    factory.connect_setup({
-        let smb_state = smb_state.clone();
-        let list_store = list_store.clone();
+    // Printers open the printer setup dialog and are installed with CUPS
+    browser.set_printer_handler({
+        let cups_service = cups_service.clone();
+        let browser = Rc::downgrade(&browser);
         let app_window_holder = app_window_holder.clone();
+        move |entry| {
+            if let (Some(parent), Some(browser)) = (app_window_holder.borrow().as_ref(), browser.upgrade()) {
+                let parent = parent.clone();
+                let cups_service = cups_service.clone();
+
+                MainContext::default().spawn_local(async move {
+                    // Without CUPS the status bar shows the error and offers to retry
+                    let cups_manager = match cups_service.manager().await {
+                        Some(cups_manager) => cups_manager,
+                        None => {
+                            browser.reporter().error("CUPS not connected, the printer can't be installed.", "");
+                            return;
+                        }
+                    };
+
+                    install_printer(parent, cups_manager, browser, entry).await;
+                });
+            }
+        }
+    });
+
+    factory.connect_setup({
+        let browser = browser.clone();
         move |_, obj| {
             let list_item = obj
                 .downcast_ref::<ListItem>()
                 .expect("Needs to be a ListItem");
 
+            // Each row shows the name and, if there is one, the comment as subtitle
+            let row = Box::new(Orientation::Vertical, 2);
+
             let label = Label::new(None);
             label.set_xalign(0.0);
 
+            let subtitle = Label::new(None);
+            subtitle.set_xalign(0.0);
+            subtitle.add_css_class("dim-label");
+            subtitle.add_css_class("caption");
+
+            row.append(&label);
+            row.append(&subtitle);
+
             let gesture = GestureClick::new();
 
             // Why do I need to clone all of these??? I Might need to look more into that, but for now it is working.
             let li = list_item.clone();
-            let smb_state_cl = smb_state.clone();
-            let list_store_cl = list_store.clone();
-            let app_window_holder_cl = app_window_holder.clone();
-            let cups_manager = cups_manager.clone();
+            let browser_cl = browser.clone();
 
             // Connect the click gesture to handle item clicks
             gesture.connect_pressed(move |_gesture, n_press, _x, _y| {
                 if n_press >= 1 {
                     if let Some(entry) = li.item().and_downcast::<SambaEntryObject>() {
-                        let server = entry.server_path();
-                        let server = match server {
-                            Some(s) => s,
-                            None => {
-                                eprintln!("No server path found for this entry");
-                                return;
-                            }
-                        };
-
-                        // Handle the click based on the entry type
-                        match entry.entry_type() {
-                            // If it's a directory, navigate into it and list its contents
-                            SambaEntryType::Directory => {
-                                if let Some(conn_rc) = smb_state_cl.borrow().as_ref() {
-                                    match conn_rc.list_directory(&server) {
-                                        Ok(entries) => {
-                                            list_store_cl.remove_all();
-                                            for entry in entries {
-                                                let server_path = server
-                                                    .join(&entry.name)
-                                                    .expect("Failed to join paths {} and {}");
-
-                                                let obj = SambaEntryObject::new(&entry, &server_path);
-                                                list_store_cl.append(&obj);
-                                            }
-                                        }
-                                        Err(e) => eprintln!("Error listing directory: {}", e),
-                                    }
-                                }
-                            }
-                            // If it's a printer, open the printer setup dialog
-                            SambaEntryType::Printer => {
-                                // Spawn the printer setup dialog on the main context
-
-                                let holder = app_window_holder_cl.clone();
-                                let cups_manager = cups_manager.clone();
-                                let smb_state_cl = smb_state_cl.clone();
-
-                                MainContext::default().spawn_local(async move {
-                                    if let Some(parent) = holder.borrow().as_ref() {
-                                        if let Some(result) = show_printer_setup_dialog(parent, &cups_manager.ppds, Option::from(entry.name())).await {
-                                            println!("Chosen: {} {} {} {}", result.manufacturer, result.model, result.printer_name, result.location);
-                                            let mut ppd_file: Option<&PpdInfo> = None;
-
-                                            // Find the matching PPD file for the selected printer
-                                            for ppd in &cups_manager.ppds {
-                                                if ppd.make == result.manufacturer && ppd.make_and_model == result.model {
-                                                    ppd_file = Some(ppd);
-                                                    break;
-                                                }
-                                            }
-                                            // Connect to the printer using the CUPS APIs
-                                            cups_manager.connect_to_printer(
-                                                smb_state_cl
-                                                    .borrow()
-                                                    .as_ref()
-                                                    .expect("Samba connection should be established")
-                                                    .credentials
-                                                    .clone(),
-                                                &server,
-                                                &result,
-                                                ppd_file
-                                            );
-                                        }
-                                    }
-                                });
-                            }
-                            // Files and unknown types are not interactable in this version, so we do nothing on click
-                            // In the future we might want to add functionality for files (e.g. open with default app, download, etc.), but there is no plan for that.
-                            _ => {}
-                        }
+                        browser_cl.activate(&entry);
                     } else {
-                        // This should never happen, but we log it just in case
-                        // Before this happens the application would already have crashed.
-                        println!("No model item found for this row");
+                        // This should never happen, before this happens the application would already have crashed
+                        browser_cl.reporter().error("The entry could not be opened.", "No model item found for this row");
                     }
                 }
             });
 
-            label.add_controller(gesture);
-            list_item.set_child(Some(&label));
+            row.add_controller(gesture);
+            list_item.set_child(Some(&row));
         }
     });
 
@@ -143,15 +117,26 @@ pub fn build_ui(application: &Application) {
             .and_downcast::<SambaEntryObject>()
             .expect("Needs to be SambaEntryObject");
 
-        let label = list_item
+        let row = list_item
             .child()
-            .unwrap()
-            .downcast::<Label>()
-            .unwrap();
+            .and_downcast::<Box>()
+            .expect("Child should be a Box");
+
+        let label = row
+            .first_child()
+            .and_downcast::<Label>()
+            .expect("First child should be the title Label");
+
+        let subtitle = row
+            .last_child()
+            .and_downcast::<Label>()
+            .expect("Last child should be the subtitle Label");
 
         // Set an icon based on the entry type
         // Note: In Clion and Rust Rover it looks like there are just empty strings, but there are really there. DON'T DELETE THEM, THEY ARE IMPORTANT FOR THE UI FOR REAL
         let icon = match entry.entry_type() {
+            SambaEntryType::Workgroup => "🌐",
+            SambaEntryType::Server => "🖥️",
             SambaEntryType::Directory => "📁",
             SambaEntryType::File => "📄",
             SambaEntryType::Printer => "🖨️",
@@ -159,6 +144,16 @@ pub fn build_ui(application: &Application) {
         };
 
         label.set_text(&format!("{} {}", icon, entry.name()));
+
+        // Printers that have a CUPS queue already say so in front of the comment
+        let installed_as = entry.installed_as();
+        let comment = match installed_as.is_empty() {
+            true => entry.comment(),
+            false if entry.comment().is_empty() => format!("✔ Installed as {}", installed_as.join(", ")),
+            false => format!("✔ Installed as {} · {}", installed_as.join(", "), entry.comment()),
+        };
+        subtitle.set_text(&comment);
+        subtitle.set_visible(!comment.is_empty());
     });
 
     let list_view = ListView::new(
@@ -186,8 +181,33 @@ pub fn build_ui(application: &Application) {
         .margin_end(6)
         .build();
 
-    vbox.append(&connect_button);
-    vbox.append(&scrolled);
+    let browse_page = Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .build();
+
+    browse_page.append(&connect_button);
+    browse_page.append(browser.toolbar());
+    browse_page.append(browser.location_entry());
+    browse_page.append(browser.loading_bar());
+    browse_page.append(&scrolled);
+
+    // The browser and the printers installed in CUPS are two pages of the window
+    let stack = Stack::builder()
+        .vexpand(true)
+        .build();
+    stack.add_titled(&browse_page, Some("browse"), "Browse");
+    stack.add_titled(installed_printers.widget(), Some("installed"), "Installed Printers");
+
+    let stack_switcher = StackSwitcher::builder()
+        .stack(&stack)
+        .halign(Align::Center)
+        .build();
+
+    vbox.append(reporter.banner());
+    vbox.append(cups_service.status_bar());
+    vbox.append(&stack_switcher);
+    vbox.append(&stack);
 
     // ---- Window ----
     let window = ApplicationWindow::builder()
@@ -199,25 +219,259 @@ pub fn build_ui(application: &Application) {
         .visible(true)
         .build();
 
+    // Navigation actions and their keyboard shortcuts
+    browser.add_actions(&window);
+    application.set_accels_for_action("win.back", &["<Alt>Left"]);
+    application.set_accels_for_action("win.forward", &["<Alt>Right"]);
+    application.set_accels_for_action("win.up", &["<Alt>Up"]);
+    application.set_accels_for_action("win.refresh", &["F5", "<Control>r"]);
+    application.set_accels_for_action("win.bookmark", &["<Control>d"]);
+
     // store window so asynchronous closures can access it later
     *app_window_holder.borrow_mut() = Some(window.clone());
+    reporter.set_window(&window);
 
     connect_button.connect_clicked(clone!(
             #[weak]
             window,
+            #[strong]
+            browser,
             move |_| {
                 MainContext::default()
-                    .spawn_local(smb_login_dialog::show_dialog(window.clone(), list_store.clone(), smb_state.clone()));
+                    .spawn_local(smb_login_dialog::show_dialog(window.clone(), browser.clone(), None, None));
             }
         ));
 
-    window.connect_close_request(|window| {
-        if let Some(app) = window.application() {
-            app.remove_window(window);
+    // Locations that need (another) login open the login dialog pre-filled
+    browser.set_login_handler(clone!(
+            #[weak]
+            window,
+            #[weak]
+            browser,
+            move |location| {
+                MainContext::default()
+                    .spawn_local(smb_login_dialog::show_dialog(window.clone(), browser.clone(), location, None));
+            }
+        ));
+
+    window.connect_close_request(clone!(
+        #[weak]
+        browser,
+        #[upgrade_or]
+        Propagation::Proceed,
+        move |window| {
+            browser.save_last_path();
+            if let Some(app) = window.application() {
+                app.remove_window(window);
+            }
+
+            Propagation::Proceed
+        }
+    ));
+
+    // Every load of the queues updates the installed printers page as well
+    browser.set_queues_handler(clone!(
+        #[weak]
+        installed_printers,
+        move |queues| installed_printers.set_queues(queues)
+    ));
+
+    // Check CUPS once the window is shown, a missing CUPS only disables installing printers.
+    // The installed printers are loaded again when their page is shown or refreshed.
+    // Showing the page again while the queues are still loading doesn't start another request.
+    let load_queues = Rc::new(clone!(
+        #[strong]
+        browser,
+        #[weak]
+        installed_printers,
+        move || {
+            if installed_printers.is_loading() {
+                return;
+            }
+            installed_printers.set_loading(true);
+
+            MainContext::default().spawn_local(clone!(
+                #[strong]
+                browser,
+                #[strong]
+                cups_service,
+                #[strong]
+                installed_printers,
+                async move {
+                    if let Some(cups_manager) = cups_service.manager().await {
+                        refresh_printer_queues(&cups_manager, &browser).await;
+                    }
+                    installed_printers.set_loading(false);
+                }
+            ));
+        }
+    ));
+
+    load_queues();
+
+    installed_printers.set_refresh_handler(clone!(
+        #[strong]
+        load_queues,
+        move || load_queues()
+    ));
+
+    stack.connect_visible_child_name_notify(move |stack| {
+        if stack.visible_child_name().as_deref() == Some("installed") {
+            load_queues();
         }
+    });
 
-        Propagation::Proceed
+    // An unreadable credentials file is reported, the login dialog opens anyway
+    let credentials = credentials_file.and_then(|path| match read_credentials_file(path) {
+        Ok(credentials) => Some(credentials),
+        Err(e) => {
+            reporter.error("The credentials file could not be read.", &e.to_string());
+            None
+        }
     });
 
-    connect_button.emit_clicked();
-}
\ No newline at end of file
+    MainContext::default().spawn_local(smb_login_dialog::show_dialog(window, browser, None, credentials));
+}
+
+// Show the printer setup dialog for `entry` and install the printer with the chosen settings
+async fn install_printer(parent: ApplicationWindow, cups_manager: Arc<CupsManager>, browser: Rc<Browser>, entry: SambaEntryObject) {
+    let reporter = browser.reporter().clone();
+
+    let server = match entry.server_path() {
+        Some(server) => server,
+        None => return,
+    };
+
+    let connection = match browser.connection() {
+        Some(connection) => connection,
+        None => {
+            reporter.error("Not connected to a SMB server.", "");
+            return;
+        }
+    };
+
+    if cups_manager.ppds.is_empty() {
+        reporter.warning("No printer drivers could be loaded from CUPS.", "CUPS-Get-PPDs returned no drivers");
+    }
+
+    // The credential mode the user chose last time stays the default.
+    // Kerberos and guest logins have no password, their printers authenticate the same way.
+    let config = Config::load();
+    let (default_auth_mode, remember_auth_mode) = match connection.credentials.auth_mode {
+        SambaAuthMode::Kerberos => (PrinterAuthMode::Negotiate, false),
+        SambaAuthMode::Guest => (PrinterAuthMode::Guest, false),
+        SambaAuthMode::Password => (config.printer_auth_mode(), true),
+    };
+    let auth_mode_locked = match connection.credentials.auth_mode {
+        SambaAuthMode::Kerberos => Some("Logged in with Kerberos, the printer uses the Kerberos ticket of the printing user."),
+        SambaAuthMode::Guest => Some("Logged in as guest, the printer connects without credentials."),
+        SambaAuthMode::Password => None,
+    };
+
+    // The queues may have changed since the list was loaded, e.g. by lpadmin
+    refresh_printer_queues(&cups_manager, &browser).await;
+    let queues = browser.printer_queues();
+
+    let host = url_escape::decode(server.host_str().unwrap_or_default()).to_string();
+    let installed_as: Vec<String> = queues.iter()
+        .filter(|queue| queue.is_for_share(&server))
+        .map(|queue| queue.name.clone())
+        .collect();
+
+    // An installed printer is edited, the dialog starts with the settings of its (first) queue
+    let current = match installed_as.first() {
+        Some(name) => match cups_manager.clone().printer_details_in_background(name.clone()).await {
+            Ok(current) => Some(current),
+            Err(e) => {
+                reporter.warning("The settings of the installed printer could not be read.", &e.to_string());
+                None
+            }
+        },
+        None => None,
+    };
+
+    let mut defaults = PrinterSetupDefaults {
+        printer_name: Option::from(entry.name()),
+        suggested_name: Option::from(suggest_queue_name(&host, &entry.name())),
+        description: Option::from(entry.comment()),
+        auth_mode: default_auth_mode,
+        auth_mode_locked: auth_mode_locked.map(str::to_string),
+        existing_queues: queues.iter().map(|queue| queue.name.clone()).collect(),
+        installed_as,
+        ..PrinterSetupDefaults::default()
+    };
+
+    if let Some(current) = &current {
+        let driver = cups_manager.ppds.iter().find(|ppd| current.uses_driver(ppd));
+
+        defaults.printer_name = Some(current.name.clone());
+        defaults.description = Some(current.info.clone());
+        defaults.location = Some(current.location.clone());
+        defaults.manufacturer = driver.map(|ppd| ppd.make.clone());
+        defaults.model = driver.map(|ppd| ppd.make_and_model.clone());
+        defaults.auth_mode = current.auth_mode();
+        // Without a password login the queue keeps the credentials it has
+        if auth_mode_locked.is_some() {
+            defaults.auth_mode_locked = Some("Log in with a password to change the credentials of this printer.".to_string());
+        }
+        // Saving under its own name is no collision
+        defaults.existing_queues.retain(|name| !name.eq_ignore_ascii_case(&current.name));
+        defaults.editing = Some(current.name.clone());
+    }
+
+    let ppds = cups_manager.ppds.clone();
+    let setup_manager = cups_manager.clone();
+    let current = current.map(Arc::new);
+    let installed = show_printer_setup_dialog(&parent, &ppds, defaults, move |result| {
+        // Find the matching PPD file for the selected printer
+        let ppd_file = setup_manager.ppds.iter()
+            .find(|ppd| ppd.make == result.manufacturer && ppd.make_and_model == result.model)
+            .cloned();
+
+        let setup_manager = setup_manager.clone();
+        let credentials = connection.credentials.clone();
+        let server = server.clone();
+        let current = current.clone();
+
+        // Connect to the printer using the CUPS APIs, on failure the dialog stays open.
+        // The edited queue only gets the changed attributes, another name sets up a new queue.
+        async move {
+            let auth_mode = result.auth_mode;
+            match current {
+                Some(current) if current.name.eq_ignore_ascii_case(&result.printer_name) => {
+                    setup_manager.update_printer_in_background(credentials, server, current, result, ppd_file).await
+                        .map(|installed| (installed, auth_mode, true))
+                        .map_err(|e| format!("The printer could not be saved.\n{}", e))
+                }
+                _ => {
+                    setup_manager.connect_to_printer_in_background(credentials, server, result, ppd_file).await
+                        .map(|installed| (installed, auth_mode, false))
+                        .map_err(|e| format!("The printer could not be installed.\n{}", e))
+                }
+            }
+        }
+    }).await;
+
+    if let Some((installed, auth_mode, updated)) = installed {
+        match updated {
+            true => reporter.info(&format!("Printer \"{}\" was saved.", installed.name)),
+            false => reporter.info(&format!("Printer \"{}\" was installed as {}.", installed.name, installed.printer_uri)),
+        }
+        refresh_printer_queues(&cups_manager, &browser).await;
+
+        if remember_auth_mode && auth_mode != config.printer_auth_mode() {
+            config.set_printer_auth_mode(auth_mode);
+            if let Err(e) = config.save() {
+                reporter.warning("The settings could not be saved.", &e.to_string());
+            }
+        }
+    }
+}
+
+// Load the queues of the CUPS server and mark the installed printers in the browser
+async fn refresh_printer_queues(cups_manager: &Arc<CupsManager>, browser: &Browser) {
+    match cups_manager.clone().printer_queues_in_background().await {
+        Ok(queues) => browser.set_printer_queues(queues.into_iter().map(Rc::new).collect()),
+        Err(e) => browser.reporter().warning("The installed printers could not be listed.", &e.to_string()),
+    }
+}
@@ -1,12 +1,13 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
 use std::rc::Rc;
 
-use glib::{clone, Object};
+use glib::{clone, MainContext, Object};
 use glib::subclass::prelude::ObjectSubclassIsExt;
 use gtk::gio::{ListModel, ListStore};
-use gtk::{prelude::*, Align, Box, Button, Entry, Frame, Grid, Label, ListItem, ListView, Orientation, PolicyType, ScrolledWindow, SignalListItemFactory, SingleSelection, Window};
+use gtk::{prelude::*, AlertDialog, Align, Box, Button, DropDown, Entry, Frame, Grid, Label, ListItem, ListView, Orientation, PolicyType, ScrolledWindow, SignalListItemFactory, SingleSelection, Spinner, Window};
 use oneshot::channel;
-use crate::cups::PpdInfo;
+use crate::cups::{unique_queue_name, validate_queue_name, PpdInfo, PrinterAuthMode};
 
 /// Result from the printer setup dialog
 #[derive(Debug, Clone)]
@@ -16,6 +17,35 @@ pub struct PrinterSetupResult {
     pub printer_name: String,
     pub description: String,
     pub location: String,
+    pub auth_mode: PrinterAuthMode,
+}
+
+/// Values the printer setup dialog starts with
+#[derive(Debug, Clone, Default)]
+pub struct PrinterSetupDefaults {
+    /// Pre-filled printer name, usually the share name
+    pub printer_name: Option<String>,
+    /// Valid queue name offered while the printer name is invalid,
+    /// also pre-filled if `printer_name` is invalid
+    pub suggested_name: Option<String>,
+    /// Pre-filled description, usually the share comment
+    pub description: Option<String>,
+    /// Pre-filled location
+    pub location: Option<String>,
+    /// Pre-selected manufacturer and model (make and model of the PPD)
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    /// Pre-selected way the printer authenticates against the server
+    pub auth_mode: PrinterAuthMode,
+    /// Why `auth_mode` can't be changed, e.g. a Kerberos login has no password to store.
+    /// None keeps the choice open.
+    pub auth_mode_locked: Option<String>,
+    /// Names of all CUPS queues, confirming one of them asks before the queue is overwritten
+    pub existing_queues: Vec<String>,
+    /// Queues that print to this share already
+    pub installed_as: Vec<String>,
+    /// Name of the queue that is edited, None when a new queue is set up
+    pub editing: Option<String>,
 }
 
 mod manufacturer_object {
@@ -119,16 +149,32 @@ fn create_list_factory() -> SignalListItemFactory {
 /// # Arguments
 /// * `parent` - The parent window for the dialog
 /// * `manufacturers` - List of available printer manufacturers and their models
+/// * `defaults` - Pre-filled values and the queues that exist already
+/// * `on_confirm` - Called with the selections when the user confirms. The dialog is busy
+///   until the returned future is done. On error the dialog stays open and shows the message,
+///   so the user can correct the input.
 /// 
 /// # Returns
-/// `Some(PrinterSetupResult)` if the user confirms, `None` if cancelled
-pub async fn show_printer_setup_dialog<W: IsA<Window>>(
+/// `Some(T)` with the result of `on_confirm` if it succeeded, `None` if cancelled
+pub async fn show_printer_setup_dialog<W, T, F, Fut>(
     parent: &W,
     manufacturers: &Vec<PpdInfo>,
-    printer_name: Option<String>,
-) -> Option<PrinterSetupResult> {
+    defaults: PrinterSetupDefaults,
+    on_confirm: F,
+) -> Option<T>
+where
+    W: IsA<Window>,
+    T: 'static,
+    F: Fn(PrinterSetupResult) -> Fut + 'static,
+    Fut: Future<Output = Result<T, String>> + 'static,
+{
+    let PrinterSetupDefaults {
+        printer_name, suggested_name, description, location, manufacturer, model, auth_mode, auth_mode_locked,
+        existing_queues, installed_as, editing,
+    } = defaults;
+
     let dialog = Window::builder()
-        .title("Printer Setup")
+        .title(if editing.is_some() { "Edit Printer" } else { "Printer Setup" })
         .modal(true)
         .transient_for(parent)
         .default_width(600)
@@ -274,12 +320,40 @@ pub async fn show_printer_setup_dialog<W: IsA<Window>>(
         .halign(Align::Start)
         .build();
 
+    // Share names often contain spaces, CUPS doesn't accept those as queue name
+    let printer_name = printer_name
+        .filter(|name| validate_queue_name(name).is_ok())
+        .or_else(|| suggested_name.clone());
+
     let name_entry = Entry::builder()
         .placeholder_text("Enter printer name")
         .text(printer_name.as_deref().unwrap_or(""))
         .hexpand(true)
         .build();
 
+    // Shown below the name as long as CUPS would reject it
+    let name_error = Label::builder()
+        .xalign(0.0)
+        .wrap(true)
+        .hexpand(true)
+        .build();
+    name_error.add_css_class("error");
+    name_error.add_css_class("caption");
+
+    let suggestion_button = Button::builder()
+        .label(format!("Use \"{}\"", suggested_name.as_deref().unwrap_or_default()))
+        .visible(false)
+        .build();
+    suggestion_button.add_css_class("flat");
+
+    let name_hint = Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .visible(false)
+        .build();
+    name_hint.append(&name_error);
+    name_hint.append(&suggestion_button);
+
     let description_label = Label::builder()
         .label("Description:")
         .halign(Align::Start)
@@ -287,6 +361,7 @@ pub async fn show_printer_setup_dialog<W: IsA<Window>>(
 
     let description_entry = Entry::builder()
         .placeholder_text("Short description of the printer (optional)")
+        .text(description.as_deref().unwrap_or(""))
         .hexpand(true)
         .build();
 
@@ -297,15 +372,41 @@ pub async fn show_printer_setup_dialog<W: IsA<Window>>(
 
     let location_entry = Entry::builder()
         .placeholder_text("Enter printer location (e.g., Office Room 101)")
+        .text(location.as_deref().unwrap_or(""))
         .hexpand(true)
         .build();
 
+    let auth_label = Label::builder()
+        .label("Credentials:")
+        .halign(Align::Start)
+        .build();
+
+    let auth_labels: Vec<&str> = PrinterAuthMode::ALL.iter().map(|mode| mode.label()).collect();
+    let auth_dropdown = DropDown::from_strings(&auth_labels);
+    auth_dropdown.set_hexpand(true);
+    if let Some(position) = PrinterAuthMode::ALL.iter().position(|mode| *mode == auth_mode) {
+        auth_dropdown.set_selected(position as u32);
+    }
+    auth_dropdown.set_sensitive(auth_mode_locked.is_none());
+
+    let auth_hint = Label::builder()
+        .label(auth_mode_locked.as_deref().unwrap_or_default())
+        .xalign(0.0)
+        .wrap(true)
+        .visible(auth_mode_locked.is_some())
+        .build();
+    auth_hint.add_css_class("dim-label");
+
     details_grid.attach(&name_label, 0, 0, 1, 1);
     details_grid.attach(&name_entry, 1, 0, 1, 1);
-    details_grid.attach(&description_label, 0, 1, 1, 1);
-    details_grid.attach(&description_entry, 1, 1, 1, 1);
-    details_grid.attach(&location_label, 0, 2, 1, 1);
-    details_grid.attach(&location_entry, 1, 2, 1, 1);
+    details_grid.attach(&name_hint, 1, 1, 1, 1);
+    details_grid.attach(&description_label, 0, 2, 1, 1);
+    details_grid.attach(&description_entry, 1, 2, 1, 1);
+    details_grid.attach(&location_label, 0, 3, 1, 1);
+    details_grid.attach(&location_entry, 1, 3, 1, 1);
+    details_grid.attach(&auth_label, 0, 4, 1, 1);
+    details_grid.attach(&auth_dropdown, 1, 4, 1, 1);
+    details_grid.attach(&auth_hint, 1, 5, 1, 1);
 
     details_frame.set_child(Some(&details_grid));
 
@@ -317,15 +418,101 @@ pub async fn show_printer_setup_dialog<W: IsA<Window>>(
         .build();
 
     let cancel_button = Button::with_label("Cancel");
-    let confirm_button = Button::with_label("Confirm");
+    let confirm_button = Button::with_label(if editing.is_some() { "Save" } else { "Confirm" });
     confirm_button.add_css_class("suggested-action");
 
+    // Spins while the printer is installed
+    let spinner = Spinner::builder()
+        .visible(false)
+        .build();
+
+    buttons_box.append(&spinner);
     buttons_box.append(&cancel_button);
     buttons_box.append(&confirm_button);
 
+    // Shows why the last confirm failed
+    let error_label = Label::builder()
+        .xalign(0.0)
+        .wrap(true)
+        .selectable(true)
+        .visible(false)
+        .build();
+    error_label.add_css_class("error");
+
+    // Printers installed before are listed with their queue names, a second queue is possible
+    let installed_label = Label::builder()
+        .xalign(0.0)
+        .wrap(true)
+        .visible(!installed_as.is_empty())
+        .build();
+    installed_label.set_text(&format!("This printer is already installed as {}.", installed_as.iter()
+        .map(|name| format!("\"{}\"", name))
+        .collect::<Vec<_>>()
+        .join(", ")));
+    installed_label.add_css_class("dim-label");
+
+    // Validate the name while it is typed, Confirm stays disabled until CUPS would accept it.
+    // Names of existing queues are allowed, but the user is warned that the queue is replaced.
+    let check_name = clone!(
+        #[weak]
+        name_hint,
+        #[weak]
+        name_error,
+        #[weak]
+        suggestion_button,
+        #[weak]
+        confirm_button,
+        #[strong]
+        suggested_name,
+        #[strong]
+        existing_queues,
+        move |name: &str| {
+            let result = validate_queue_name(name);
+            let existing = existing_queues.iter().find(|queue| queue.eq_ignore_ascii_case(name));
+            confirm_button.set_sensitive(result.is_ok());
+
+            match (result, existing) {
+                (Err(e), _) => {
+                    name_error.set_css_classes(&["caption", "error"]);
+                    name_error.set_text(&e.to_string());
+                    suggestion_button.set_visible(suggested_name.as_deref().is_some_and(|suggested| suggested != name));
+                    name_hint.set_visible(true);
+                }
+                (Ok(()), Some(queue)) => {
+                    name_error.set_css_classes(&["caption", "warning"]);
+                    name_error.set_text(&format!("A printer named \"{}\" exists already.", queue));
+                    suggestion_button.set_visible(false);
+                    name_hint.set_visible(true);
+                }
+                (Ok(()), None) => name_hint.set_visible(false),
+            }
+        }
+    );
+
+    name_entry.connect_changed(clone!(
+        #[strong]
+        check_name,
+        move |entry| check_name(&entry.text())
+    ));
+    check_name(&name_entry.text());
+
+    suggestion_button.connect_clicked(clone!(
+        #[weak]
+        name_entry,
+        #[strong]
+        suggested_name,
+        move |_| {
+            if let Some(suggested) = suggested_name.as_deref() {
+                name_entry.set_text(suggested);
+            }
+        }
+    ));
+
     // Assemble main layout
     main_vbox.append(&lists_hbox);
+    main_vbox.append(&installed_label);
     main_vbox.append(&details_frame);
+    main_vbox.append(&error_label);
     main_vbox.append(&buttons_box);
 
     dialog.set_child(Some(&main_vbox));
@@ -368,17 +555,57 @@ pub async fn show_printer_setup_dialog<W: IsA<Window>>(
         }
     });
 
-    // Select first manufacturer by default
+    // Select the driver of an edited printer, else the first manufacturer
+    let manufacturer_position = manufacturer_store.iter::<ManufacturerObject>()
+        .position(|item| item.is_ok_and(|item| Some(item.name()) == manufacturer));
+
     if manufacturer_store.n_items() > 0 {
-        manufacturer_selection.set_selected(0);
+        manufacturer_selection.set_selected(manufacturer_position.unwrap_or_default() as u32);
+    }
+
+    let model_position = model_store.iter::<ModelObject>()
+        .position(|item| item.is_ok_and(|item| Some(item.name()) == model));
+
+    if let (Some(position), Some(model)) = (model_position, model) {
+        model_selection.set_selected(position as u32);
+        *selected_model.borrow_mut() = Some(model);
     }
 
     dialog.set_visible(true);
 
     // --- Async result handling ---
-    let (tx, rx) = channel::<Option<PrinterSetupResult>>();
+    let (tx, rx) = channel::<Option<T>>();
     let tx = Rc::new(RefCell::new(Some(tx)));
 
+    // The dialog can't be closed while CUPS installs the printer, the result would be lost
+    let busy = Rc::new(Cell::new(false));
+    dialog.connect_close_request(clone!(
+        #[strong]
+        busy,
+        move |_| match busy.get() {
+            true => glib::Propagation::Stop,
+            false => glib::Propagation::Proceed,
+        }
+    ));
+
+    let set_busy = Rc::new(clone!(
+        #[weak]
+        spinner,
+        #[weak]
+        cancel_button,
+        #[weak]
+        confirm_button,
+        #[strong]
+        busy,
+        move |value: bool| {
+            busy.set(value);
+            spinner.set_visible(value);
+            spinner.set_spinning(value);
+            cancel_button.set_sensitive(!value);
+            confirm_button.set_sensitive(!value);
+        }
+    ));
+
     cancel_button.connect_clicked(clone!(
         #[weak]
         dialog,
@@ -395,13 +622,18 @@ pub async fn show_printer_setup_dialog<W: IsA<Window>>(
     let selected_manufacturer_final = selected_manufacturer.clone();
     let selected_model_final = selected_model.clone();
 
+    // Called from the click handler and from the task that asks before a queue is overwritten
+    let on_confirm = Rc::new(on_confirm);
+
     confirm_button.connect_clicked(clone!(
         #[weak]
         dialog,
         #[strong]
         tx,
+        #[strong]
+        busy,
         move |_| {
-            if let Some(sender) = tx.borrow_mut().take() {
+            if tx.borrow().is_some() && !busy.get() {
                 let manufacturer = selected_manufacturer_final
                     .borrow()
                     .clone()
@@ -410,6 +642,10 @@ pub async fn show_printer_setup_dialog<W: IsA<Window>>(
                 let printer_name = name_entry.text().to_string();
                 let description = description_entry.text().to_string();
                 let location = location_entry.text().to_string();
+                let auth_mode = PrinterAuthMode::ALL
+                    .get(auth_dropdown.selected() as usize)
+                    .copied()
+                    .unwrap_or_default();
 
                 let result = PrinterSetupResult {
                     manufacturer,
@@ -417,11 +653,71 @@ pub async fn show_printer_setup_dialog<W: IsA<Window>>(
                     printer_name,
                     description,
                     location,
+                    auth_mode,
                 };
 
-                let _ = sender.send(Some(result));
+                let existing = existing_queues.iter()
+                    .find(|queue| queue.eq_ignore_ascii_case(&result.printer_name))
+                    .cloned();
+
+                MainContext::default().spawn_local(clone!(
+                    #[weak]
+                    dialog,
+                    #[weak]
+                    name_entry,
+                    #[weak]
+                    error_label,
+                    #[strong]
+                    tx,
+                    #[strong]
+                    on_confirm,
+                    #[strong]
+                    existing_queues,
+                    #[strong]
+                    set_busy,
+                    async move {
+                        // CUPS-Add-Modify-Printer would replace the existing queue without asking
+                        if let Some(existing) = existing {
+                            let alert = AlertDialog::builder()
+                                .modal(true)
+                                .message(format!("A printer named \"{}\" exists already", existing))
+                                .detail("Overwrite replaces its driver and settings, Rename picks another name.")
+                                .buttons(["Cancel", "Rename", "Overwrite"])
+                                .cancel_button(0)
+                                .default_button(0)
+                                .build();
+
+                            match alert.choose_future(Some(&dialog)).await {
+                                Ok(2) => {}
+                                Ok(1) => {
+                                    name_entry.set_text(&unique_queue_name(&result.printer_name, &existing_queues));
+                                    name_entry.grab_focus();
+                                    return;
+                                }
+                                _ => return,
+                            }
+                        }
+
+                        set_busy(true);
+                        error_label.set_visible(false);
+                        let confirmed = on_confirm(result).await;
+                        set_busy(false);
+
+                        match confirmed {
+                            Ok(value) => {
+                                if let Some(sender) = tx.borrow_mut().take() {
+                                    let _ = sender.send(Some(value));
+                                }
+                                dialog.close();
+                            }
+                            Err(message) => {
+                                error_label.set_text(&message);
+                                error_label.set_visible(true);
+                            }
+                        }
+                    }
+                ));
             }
-            dialog.close();
         }
     ));
 
@@ -15,7 +15,10 @@ mod imp {
     pub struct SambaEntryObject {
         pub name: RefCell<String>,
         pub entry_type: RefCell<SambaEntryType>,
+        pub comment: RefCell<String>,
         pub server_path: RefCell<Option<Url>>,
+        // Names of the CUPS queues that print to this share
+        pub installed_as: RefCell<Vec<String>>,
     }
 
     #[glib::object_subclass]
@@ -36,6 +39,7 @@ impl SambaEntryObject {
         let obj: Self = glib::Object::new();
         obj.imp().name.replace(entry.name.clone());
         obj.imp().entry_type.replace(entry.entry_type);
+        obj.imp().comment.replace(entry.comment.clone());
         obj.imp().server_path.replace(Some(server_path.clone()));
 
         obj
@@ -49,7 +53,19 @@ impl SambaEntryObject {
         (*self.imp().entry_type.borrow()).clone()
     }
 
+    pub fn comment(&self) -> String {
+        self.imp().comment.borrow().to_string()
+    }
+
     pub fn server_path(&self) -> Option<Url> {
         self.imp().server_path.borrow().clone()
     }
+
+    pub fn installed_as(&self) -> Vec<String> {
+        self.imp().installed_as.borrow().clone()
+    }
+
+    pub fn set_installed_as(&self, queues: Vec<String>) {
+        self.imp().installed_as.replace(queues);
+    }
 }
\ No newline at end of file
@@ -1,22 +1,31 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::clone::Clone;
-use std::ops::Deref;
 use std::option::Option;
 use std::option::Option::{None, Some};
 use std::prelude::v1::{Err, Ok};
 use std::rc::Rc;
 use std::string::String;
 
-use crate::gui::samba_entry_object::SambaEntryObject;
-use crate::smb::{SambaConnection, SambaCredentials};
-use glib::clone;
-use gtk::gio::ListStore;
-use gtk::{prelude::*, Align, Button, Entry, Grid, Label, Orientation, PasswordEntry, Window};
+use crate::config::{Config, SavedServer};
+use crate::gui::browser::Browser;
+use crate::secrets::SecretStore;
+use crate::smb::{parse_location, read_credentials_file, SambaAuthMode, SambaConnectionOptions, SambaCredentials, SambaEncryption, SambaError, SambaLocation, SambaPassword, SambaProtocol, SambaSigning, SambaWorker};
+use glib::{clone, MainContext};
+use gtk::{prelude::*, Align, Button, CheckButton, DropDown, Entry, Expander, FileDialog, Grid, Label, Orientation, PasswordEntry, SpinButton, Window};
 use oneshot::channel;
-use url::Url;
+use url::Position;
 
-// This is the
-pub async fn show_dialog<W: IsA<Window>>(parent: W, list_store: ListStore, smb_state: Rc<RefCell<Option<Rc<SambaConnection>>>>) {
+// Login modes offered in the dialog, in the order of the drop-down
+const LOGIN_MODES: [(SambaAuthMode, &str); 3] = [
+    (SambaAuthMode::Password, "Password"),
+    (SambaAuthMode::Kerberos, "Kerberos (existing ticket)"),
+    (SambaAuthMode::Guest, "Connect as guest"),
+];
+
+// This is the login dialog. If a location is given, its server, user and domain are pre-filled
+// and the browser opens the location after a successful login.
+// Credentials, e.g. from a credentials file given on the command line, pre-fill the login.
+pub async fn show_dialog<W: IsA<Window>>(parent: W, browser: Rc<Browser>, location: Option<SambaLocation>, credentials: Option<SambaCredentials>) {
     let dialog = Window::builder()
         .title("SMB Authentication")
         .modal(true)
@@ -40,7 +49,7 @@ pub async fn show_dialog<W: IsA<Window>>(parent: W, list_store: ListStore, smb_s
         .build();
 
     let server = Entry::builder()
-        .placeholder_text("Server address")
+        .placeholder_text("Server address (empty to browse the network)")
         .build();
     let username = Entry::builder()
         .build();
@@ -49,26 +58,303 @@ pub async fn show_dialog<W: IsA<Window>>(parent: W, list_store: ListStore, smb_s
     let domain = Entry::builder()
         .placeholder_text("WORKGROUP (optional)")
         .build();
+    let remember = CheckButton::with_label("Remember password");
+    let import = Button::with_label("Import from file…");
+    import.set_tooltip_text(Some("Read username, password and domain from a credentials file as used by smbclient -A"));
+
+    // Set once a password was found in the keyring, so unticking "Remember password" removes it
+    let stored = Rc::new(Cell::new(false));
+
+    let login_labels: Vec<&str> = LOGIN_MODES.iter().map(|(_, label)| *label).collect();
+    let login_mode = DropDown::from_strings(&login_labels);
+
+    // Kerberos takes the ticket from the credential cache, there is no password to type.
+    // Guests need neither username nor password.
+    login_mode.connect_selected_notify(clone!(
+        #[weak]
+        username,
+        #[weak]
+        password,
+        #[weak]
+        remember,
+        move |login_mode| {
+            let mode = LOGIN_MODES.get(login_mode.selected() as usize).map(|(mode, _)| *mode);
+            username.set_sensitive(mode != Some(SambaAuthMode::Guest));
+            password.set_sensitive(mode == Some(SambaAuthMode::Password));
+            remember.set_sensitive(mode == Some(SambaAuthMode::Password));
+        }
+    ));
+
+    let show_credentials = clone!(
+        #[weak]
+        username,
+        #[weak]
+        password,
+        #[weak]
+        domain,
+        #[weak]
+        login_mode,
+        move |credentials: &SambaCredentials| {
+            username.set_text(&credentials.username);
+            password.set_text(credentials.password.as_str());
+            domain.set_text(&credentials.workgroup);
+            if let Some(position) = LOGIN_MODES.iter().position(|(mode, _)| *mode == credentials.auth_mode) {
+                login_mode.set_selected(position as u32);
+            }
+        }
+    );
+
+    import.connect_clicked(clone!(
+        #[weak]
+        dialog,
+        #[strong]
+        browser,
+        #[strong]
+        show_credentials,
+        move |_| {
+            let show_credentials = show_credentials.clone();
+            let browser = browser.clone();
+
+            MainContext::default().spawn_local(clone!(
+                #[weak]
+                dialog,
+                async move {
+                    let file_dialog = FileDialog::builder()
+                        .title("Import credentials")
+                        .modal(true)
+                        .build();
+
+                    // Closing the file dialog is not an error
+                    let path = match file_dialog.open_future(Some(&dialog)).await.ok().and_then(|file| file.path()) {
+                        Some(path) => path,
+                        None => return,
+                    };
+
+                    match read_credentials_file(&path) {
+                        Ok(credentials) => show_credentials(&credentials),
+                        Err(e) => browser.reporter().error("The credentials could not be imported.", &e.to_string()),
+                    }
+                }
+            ));
+        }
+    ));
+
+    // ---- Advanced connection options ----
+    let min_protocol = DropDown::from_strings(&SambaProtocol::ALL.map(|p| p.label()));
+    let max_protocol = DropDown::from_strings(&SambaProtocol::ALL.map(|p| p.label()));
+    let signing = DropDown::from_strings(&SambaSigning::ALL.map(|s| s.label()));
+    // libsmbclient keeps these three for the whole process, not per connection
+    for process_wide in [&min_protocol, &max_protocol, &signing] {
+        process_wide.set_tooltip_text(Some("Applies to all connections of this application"));
+    }
+    let encryption = DropDown::from_strings(&SambaEncryption::ALL.map(|e| e.label()));
+    let port = SpinButton::with_range(0.0, u16::MAX as f64, 1.0);
+    port.set_tooltip_text(Some("0 uses the default ports 445 and 139"));
+    let timeout = SpinButton::with_range(0.0, 600.0, 1.0);
+    timeout.set_tooltip_text(Some("Seconds, 0 uses the default timeout"));
+
+    let advanced_grid = Grid::builder()
+        .row_spacing(6)
+        .column_spacing(6)
+        .margin_top(6)
+        .build();
+
+    advanced_grid.attach(&Label::new(Some("Minimum protocol:")), 0, 0, 1, 1);
+    advanced_grid.attach(&min_protocol, 1, 0, 1, 1);
+
+    advanced_grid.attach(&Label::new(Some("Maximum protocol:")), 0, 1, 1, 1);
+    advanced_grid.attach(&max_protocol, 1, 1, 1, 1);
+
+    advanced_grid.attach(&Label::new(Some("Signing:")), 0, 2, 1, 1);
+    advanced_grid.attach(&signing, 1, 2, 1, 1);
+
+    advanced_grid.attach(&Label::new(Some("Encryption:")), 0, 3, 1, 1);
+    advanced_grid.attach(&encryption, 1, 3, 1, 1);
+
+    advanced_grid.attach(&Label::new(Some("Port:")), 0, 4, 1, 1);
+    advanced_grid.attach(&port, 1, 4, 1, 1);
+
+    advanced_grid.attach(&Label::new(Some("Timeout:")), 0, 5, 1, 1);
+    advanced_grid.attach(&timeout, 1, 5, 1, 1);
+
+    let process_wide_note = Label::builder()
+        .label("Protocols and signing are shared by all connections, the last login sets them.")
+        .wrap(true)
+        .xalign(0.0)
+        .build();
+    process_wide_note.add_css_class("dim-label");
+    advanced_grid.attach(&process_wide_note, 0, 6, 2, 1);
+
+    let advanced = Expander::builder()
+        .label("Advanced")
+        .child(&advanced_grid)
+        .build();
+
+    let show_options = clone!(
+        #[weak]
+        min_protocol,
+        #[weak]
+        max_protocol,
+        #[weak]
+        signing,
+        #[weak]
+        encryption,
+        #[weak]
+        port,
+        #[weak]
+        timeout,
+        move |options: &SambaConnectionOptions| {
+            let position = |found: Option<usize>| found.unwrap_or_default() as u32;
+            min_protocol.set_selected(position(SambaProtocol::ALL.iter().position(|p| *p == options.min_protocol)));
+            max_protocol.set_selected(position(SambaProtocol::ALL.iter().position(|p| *p == options.max_protocol)));
+            signing.set_selected(position(SambaSigning::ALL.iter().position(|s| *s == options.signing)));
+            encryption.set_selected(position(SambaEncryption::ALL.iter().position(|e| *e == options.encryption)));
+            port.set_value(options.port as f64);
+            timeout.set_value((options.timeout_ms / 1000) as f64);
+        }
+    );
+
+    // Servers the user connected to before get their options back
+    let config = Config::load();
+    server.connect_changed(clone!(
+        #[strong]
+        show_options,
+        move |server| {
+            if let Some(saved) = config.saved_server(&server_host(&server.text())) {
+                show_options(&saved.options);
+            }
+        }
+    ));
+
+    // ---- Saved servers ----
+    // The first entry keeps the fields as they are, the others fill in a saved login
+    let saved_servers = Config::load().saved_servers();
+    let mut saved_labels = vec!["New server".to_string()];
+    saved_labels.extend(saved_servers.iter().map(|saved| match saved.auth_mode {
+        _ if saved.address.is_empty() => "Whole network".to_string(),
+        SambaAuthMode::Password | SambaAuthMode::Kerberos if !saved.username.is_empty() => {
+            format!("{}@{}", saved.username, saved.address)
+        }
+        _ => saved.address.clone(),
+    }));
+    let saved_labels: Vec<&str> = saved_labels.iter().map(String::as_str).collect();
+    let saved = DropDown::from_strings(&saved_labels);
+
+    saved.connect_selected_notify(clone!(
+        #[weak]
+        server,
+        #[weak]
+        username,
+        #[weak]
+        password,
+        #[weak]
+        domain,
+        #[weak]
+        login_mode,
+        #[weak]
+        remember,
+        #[strong]
+        show_options,
+        #[strong]
+        stored,
+        move |saved| {
+            let index = saved.selected() as usize;
+            if let Some(saved) = index.checked_sub(1).and_then(|i| saved_servers.get(i)) {
+                server.set_text(&saved.address);
+                username.set_text(&saved.username);
+                password.set_text("");
+                domain.set_text(&saved.domain);
+                remember.set_active(false);
+                stored.set(false);
+                if let Some(position) = LOGIN_MODES.iter().position(|(mode, _)| *mode == saved.auth_mode) {
+                    login_mode.set_selected(position as u32);
+                }
+                show_options(&saved.options);
+
+                if saved.auth_mode == SambaAuthMode::Password {
+                    let host = server_host(&saved.address);
+                    let domain = saved.domain.clone();
+                    let stored = stored.clone();
+
+                    MainContext::default().spawn_local(clone!(
+                        #[weak]
+                        password,
+                        #[weak]
+                        remember,
+                        async move {
+                            // Without a keyring the password is simply typed in
+                            let found = match SecretStore::connect().await {
+                                Ok(store) => store.lookup(&host, &domain).await.ok().flatten(),
+                                Err(_) => None,
+                            };
+
+                            if let Some(found) = found {
+                                password.set_text(found.as_str());
+                                remember.set_active(true);
+                                stored.set(true);
+                            }
+                        }
+                    ));
+                }
+            }
+        }
+    ));
 
     // Pre-fill fields if smb_state has existing connection
-    if let Some(conn) = &smb_state.borrow().deref() {
+    if let Some(conn) = browser.connection() {
         server.set_text(&conn.server_root);
         username.set_text(&conn.credentials.username);
-        password.set_text(&conn.credentials.password);
         domain.set_text(&conn.credentials.workgroup);
+        if let Some(position) = LOGIN_MODES.iter().position(|(mode, _)| *mode == conn.credentials.auth_mode) {
+            login_mode.set_selected(position as u32);
+        }
+        show_options(&conn.options);
     }
 
-    grid.attach(&Label::new(Some("Server:")), 0, 0, 1, 1);
-    grid.attach(&server, 1, 0, 1, 1);
+    // Without anything else to go on, continue with the server that was used last
+    if browser.connection().is_none() && location.is_none() && credentials.is_none() && saved_labels.len() > 1 {
+        saved.set_selected(1);
+    }
 
-    grid.attach(&Label::new(Some("Username:")), 0, 1, 1, 1);
-    grid.attach(&username, 1, 1, 1, 1);
+    if let Some(credentials) = &credentials {
+        show_credentials(credentials);
+    }
 
-    grid.attach(&Label::new(Some("Password:")), 0, 2, 1, 1);
-    grid.attach(&password, 1, 2, 1, 1);
+    if let Some(location) = &location {
+        server.set_text(&location.url[Position::BeforeHost..Position::AfterPort]);
+        if let Some(user) = &location.username {
+            username.set_text(user);
+            password.set_text("");
+        }
+        if let Some(workgroup) = &location.workgroup {
+            domain.set_text(workgroup);
+        }
+    }
+
+    // The history is only shown once there is one
+    if saved_labels.len() > 1 {
+        grid.attach(&Label::new(Some("Recent:")), 0, 0, 1, 1);
+        grid.attach(&saved, 1, 0, 1, 1);
+    }
+
+    grid.attach(&Label::new(Some("Server:")), 0, 1, 1, 1);
+    grid.attach(&server, 1, 1, 1, 1);
+
+    grid.attach(&Label::new(Some("Login:")), 0, 2, 1, 1);
+    grid.attach(&login_mode, 1, 2, 1, 1);
+
+    grid.attach(&Label::new(Some("Username:")), 0, 3, 1, 1);
+    grid.attach(&username, 1, 3, 1, 1);
+
+    grid.attach(&Label::new(Some("Password:")), 0, 4, 1, 1);
+    grid.attach(&password, 1, 4, 1, 1);
 
-    grid.attach(&Label::new(Some("Domain:")), 0, 3, 1, 1);
-    grid.attach(&domain, 1, 3, 1, 1);
+    grid.attach(&remember, 1, 5, 1, 1);
+
+    grid.attach(&Label::new(Some("Domain:")), 0, 6, 1, 1);
+    grid.attach(&domain, 1, 6, 1, 1);
+
+    grid.attach(&import, 1, 7, 1, 1);
 
     let buttons = gtk::Box::builder()
         .orientation(Orientation::Horizontal)
@@ -83,12 +369,13 @@ pub async fn show_dialog<W: IsA<Window>>(parent: W, list_store: ListStore, smb_s
     buttons.append(&login);
 
     vbox.append(&grid);
+    vbox.append(&advanced);
     vbox.append(&buttons);
     dialog.set_child(Some(&vbox));
     dialog.set_visible(true);
 
     // ---- async result ----
-    let (tx, rx) = channel::<Option<(String, String, String, String)>>();
+    let (tx, rx) = channel::<Option<(String, SambaCredentials, SambaConnectionOptions, bool)>>();
     let tx = Rc::new(RefCell::new(Some(tx)));
 
     // Cancel button
@@ -110,58 +397,132 @@ pub async fn show_dialog<W: IsA<Window>>(parent: W, list_store: ListStore, smb_s
             dialog,
             move |_| {
                 if let Some(sender) = tx.borrow_mut().take() {
-                    let creds = (
-                        server.text().to_string(),
-                        username.text().to_string(),
-                        password.text().to_string(),
-                        domain.text().to_string(),
-                    );
-                    let _ = sender.send(Some(creds));
+                    let auth_mode = LOGIN_MODES
+                        .get(login_mode.selected() as usize)
+                        .map(|(mode, _)| *mode)
+                        .unwrap_or_default();
+
+                    // No password is kept for Kerberos and guest logins
+                    let (username, password) = match auth_mode {
+                        SambaAuthMode::Password => (username.text().to_string(), SambaPassword::from(password.text().as_str())),
+                        SambaAuthMode::Kerberos => (username.text().to_string(), SambaPassword::default()),
+                        SambaAuthMode::Guest => (String::new(), SambaPassword::default()),
+                    };
+
+                    let creds = SambaCredentials {
+                        workgroup: domain.text().to_string(),
+                        username,
+                        password,
+                        auth_mode,
+                    };
+                    let options = SambaConnectionOptions {
+                        min_protocol: SambaProtocol::ALL.get(min_protocol.selected() as usize).copied().unwrap_or_default(),
+                        max_protocol: SambaProtocol::ALL.get(max_protocol.selected() as usize).copied().unwrap_or_default(),
+                        signing: SambaSigning::ALL.get(signing.selected() as usize).copied().unwrap_or_default(),
+                        encryption: SambaEncryption::ALL.get(encryption.selected() as usize).copied().unwrap_or_default(),
+                        port: port.value_as_int() as u16,
+                        timeout_ms: timeout.value_as_int() as u32 * 1000,
+                    };
+
+                    let _ = sender.send(Some((server.text().to_string(), creds, options, remember.is_active())));
                 }
                 dialog.close();
             }
         ));
 
     // ---- await result ----
-    if let Ok(Some((server, user, pass, domain))) = rx.await {
-        let mut server = server;
+    if let Ok(Some((server, creds, mut options, remember))) = rx.await {
+        let server_location = match parse_location(&server) {
+            Ok(server_location) => server_location,
+            Err(e) => {
+                browser.reporter().error(&format!("\"{}\" is not a valid server address.", server), &e.to_string());
+                return;
+            }
+        };
+
+        // A port in the address wins over the default port
+        if options.port == 0 {
+            options.port = server_location.url.port().unwrap_or_default();
+        }
 
-        let creds = SambaCredentials {
-            workgroup: domain,
-            username: user,
-            password: pass,
+        // Remembered for the next start, the password is never written to the config file
+        let config = Config::load();
+        let host = server_location.url.host_str().unwrap_or_default().to_string();
+        let last_path = config.saved_server(&host).and_then(|saved| saved.last_path);
+        let saved = SavedServer {
+            address: server.clone(),
+            domain: creds.workgroup.clone(),
+            username: creds.username.clone(),
+            auth_mode: creds.auth_mode,
+            options,
+            last_path: last_path.clone(),
+            last_used: glib::real_time() / 1_000_000,
         };
 
         // Attempt to connect to SMB server with provided credentials
-        match SambaConnection::connect(creds, &server) {
+        match SambaWorker::connect(creds, &server, options) {
             Ok(conn) => {
+                // Shared with the connection, so the password isn't copied for the keyring
+                let credentials = conn.credentials.clone();
+                browser.set_connection(conn);
 
-                if !server.starts_with("smb://") {
-                    server = format!("smb://{}", server);
-                }
-
-                let server_url = Url::parse(&server).expect("Invalid SMB URL");
+                // Server and password are only kept once the server accepted the login,
+                // a mistyped password must not be filled in next time
+                let reporter = browser.reporter().clone();
+                let forget = stored.get();
+                browser.set_pending_login(move || {
+                    let config = Config::load();
+                    config.save_server(&host, &saved);
+                    if let Err(e) = config.save() {
+                        reporter.warning("The server could not be saved.", &e.to_string());
+                    }
 
-                let conn_rc = Rc::new(conn);
-                *smb_state.borrow_mut() = Some(conn_rc.clone());
+                    // The password goes to the keyring of the user, or is removed from it again
+                    if saved.auth_mode == SambaAuthMode::Password && (remember || forget) {
+                        MainContext::default().spawn_local(async move {
+                            let result = match SecretStore::connect().await {
+                                Ok(store) if remember => store.store(&host, &saved.domain, &saved.username, credentials.password.as_str()).await,
+                                Ok(store) => store.delete(&host, &saved.domain).await,
+                                Err(e) => Err(e),
+                            };
 
-                // List the root directory of the SMB server and populate the list store
-                match conn_rc.list_directory(&server_url) {
-                    Ok(entries) => {
-                        for entry in entries {
-                            let sever_url = server_url.join(&entry.name).ok();
-                            if sever_url.is_some() {
-                                let obj = SambaEntryObject::new(&entry, sever_url.as_ref().unwrap());
-                                list_store.append(&obj);
+                            if let Err(e) = result {
+                                reporter.warning("The password could not be saved in the keyring.", &e.to_string());
                             }
-                        }
+                        });
                     }
-                    Err(e) => eprintln!("Error listing directory: {}", e),
+                });
+
+                // Open the requested location if it is still on the same server,
+                // else continue where the user left the server last time or list its root directory
+                match location {
+                    Some(location) if location.url.host_str() == server_location.url.host_str() => {
+                        browser.open_url(location.url);
+                    }
+                    _ => match last_path {
+                        Some(last_path) if location.is_none() && last_path.host_str() == server_location.url.host_str() => {
+                            browser.open_url(last_path);
+                        }
+                        _ => browser.open_url(server_location.url),
+                    },
                 }
             }
+            // Settings that can't work together, the user can correct them in the next login
+            Err(e @ SambaError::InvalidProtocolRange(..)) => {
+                browser.reporter().error("The connection settings are invalid.", &e.to_string());
+            }
+            // The SMB client itself could not be set up, nothing can be browsed
             Err(e) => {
-                eprintln!("Error connecting to SMB: {}", e);
+                browser.reporter().fatal(&format!("Error connecting to SMB: {}", e), &format!("{:?}", e));
             }
         }
     }
 }
+
+// Host part of a server address as typed in the dialog, empty if it can't be parsed
+fn server_host(server: &str) -> String {
+    parse_location(server)
+        .ok()
+        .and_then(|location| location.url.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
@@ -1,4 +1,6 @@
 pub mod smb;
 pub mod gui;
 pub mod cups;
-pub mod c_helper_fun;
\ No newline at end of file
+pub mod c_helper_fun;
+pub mod config;
+pub mod secrets;
\ No newline at end of file
@@ -1,4 +1,8 @@
-use std::path::Path;
+use std::cell::RefCell;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use glib::{OptionArg, OptionFlags};
 use gtk::gdk::Display;
 use gtk::{Application, IconTheme, Window};
 use gtk::prelude::*;
@@ -22,7 +26,27 @@ fn main() -> glib::ExitCode {
         }
     });
 
+    // --credentials-file logs in with a smbclient style credentials file, e.g. for kiosk setups
+    application.add_main_option(
+        "credentials-file",
+        glib::Char::from(b'A'),
+        OptionFlags::NONE,
+        OptionArg::Filename,
+        "Read username, password and domain from FILE (format of smbclient -A)",
+        Some("FILE"),
+    );
+
+    let credentials_file: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    application.connect_handle_local_options({
+        let credentials_file = credentials_file.clone();
+        move |_, options| {
+            *credentials_file.borrow_mut() = options.lookup::<PathBuf>("credentials-file").ok().flatten();
+            // Continue with the default handling
+            ControlFlow::Continue(())
+        }
+    });
+
     // Build the UI when the application is activated
-    application.connect_activate(build_ui);
+    application.connect_activate(move |application| build_ui(application, credentials_file.borrow().as_deref()));
     application.run()
 }
\ No newline at end of file
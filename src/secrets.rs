@@ -0,0 +1,241 @@
+// Passwords of SMB servers, stored in the keyring of the user through the freedesktop Secret Service API
+// (GNOME Keyring, KWallet, KeePassXC, ...). See https://specifications.freedesktop.org/secret-service-spec/
+//
+// Only the parts of the API needed here are implemented: a "plain" session, the default collection,
+// searching, creating and deleting items. Secrets are never written to the config file.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use glib::variant::ObjectPath;
+use glib::prelude::*;
+use glib::{Variant, VariantTy};
+use gtk::gio::{self, BusType, DBusCallFlags, DBusConnection, DBusSignalFlags, IOErrorEnum};
+use zeroize::Zeroize;
+use crate::smb::SambaPassword;
+
+// Well-known name of the Secret Service on the session bus
+pub const SECRET_SERVICE_NAME: &str = "org.freedesktop.secrets";
+
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const DEFAULT_COLLECTION_PATH: &str = "/org/freedesktop/secrets/aliases/default";
+const SERVICE_INTERFACE: &str = "org.freedesktop.Secret.Service";
+const COLLECTION_INTERFACE: &str = "org.freedesktop.Secret.Collection";
+const ITEM_INTERFACE: &str = "org.freedesktop.Secret.Item";
+const PROMPT_INTERFACE: &str = "org.freedesktop.Secret.Prompt";
+
+// Value of the "application" attribute, tells our items apart from the ones of other applications
+const APPLICATION: &str = "samba-printer-finder";
+
+// Unlocking can show a password prompt of the keyring, so there is no timeout.
+// GDBus waits forever for G_MAXINT, -1 would be its default of about 25 s.
+const CALL_TIMEOUT_MS: i32 = i32::MAX;
+
+pub struct SecretStore {
+    connection: DBusConnection,
+    bus_name: String,
+    session: ObjectPath,
+}
+
+impl SecretStore {
+    // Connect to the Secret Service on the session bus
+    pub async fn connect() -> Result<Self, glib::Error> {
+        let connection = gio::bus_get_future(BusType::Session).await?;
+        SecretStore::with_connection(connection, SECRET_SERVICE_NAME).await
+    }
+
+    // Connect to a Secret Service with another bus name or on another bus, e.g. a mock service in tests
+    pub async fn with_connection(connection: DBusConnection, bus_name: &str) -> Result<Self, glib::Error> {
+        // The "plain" algorithm sends the secret unencrypted, which is fine on the local session bus
+        let reply = connection.call_future(
+            Some(bus_name),
+            SERVICE_PATH,
+            SERVICE_INTERFACE,
+            "OpenSession",
+            Some(&("plain", "".to_variant()).to_variant()),
+            Some(VariantTy::new("(vo)").unwrap()),
+            DBusCallFlags::NONE,
+            CALL_TIMEOUT_MS,
+        ).await?;
+
+        let session = reply.child_value(1).get::<ObjectPath>()
+            .ok_or_else(|| invalid_reply("OpenSession"))?;
+
+        Ok(SecretStore { connection, bus_name: bus_name.to_string(), session })
+    }
+
+    // Store the password for `server` and `domain`, an existing password is replaced
+    pub async fn store(&self, server: &str, domain: &str, username: &str, password: &str) -> Result<(), glib::Error> {
+        let collection = object_path(DEFAULT_COLLECTION_PATH)?;
+        self.unlock(vec![collection.clone()]).await?;
+
+        let label = match domain.is_empty() {
+            true => format!("SMB password of {} on {}", username, server),
+            false => format!("SMB password of {}\\{} on {}", domain, username, server),
+        };
+
+        let mut properties: HashMap<String, Variant> = HashMap::new();
+        properties.insert("org.freedesktop.Secret.Item.Label".to_string(), label.to_variant());
+        properties.insert("org.freedesktop.Secret.Item.Attributes".to_string(), attributes(server, domain).to_variant());
+
+        let mut secret = (
+            self.session.clone(),
+            Vec::<u8>::new(),
+            password.as_bytes().to_vec(),
+            "text/plain".to_string(),
+        );
+
+        // The message has a copy of its own, the bytes of the password are wiped right away
+        let parameters = (properties, &secret, true).to_variant();
+        secret.2.zeroize();
+
+        let reply = self.call(&collection, COLLECTION_INTERFACE, "CreateItem", parameters, "(oo)").await?;
+
+        let prompt = reply.child_value(1).get::<ObjectPath>()
+            .ok_or_else(|| invalid_reply("CreateItem"))?;
+        self.prompt(&prompt).await?;
+
+        Ok(())
+    }
+
+    // The stored password for `server` and `domain`, None if there is none
+    pub async fn lookup(&self, server: &str, domain: &str) -> Result<Option<SambaPassword>, glib::Error> {
+        let items = self.search(server, domain).await?;
+        if items.is_empty() {
+            return Ok(None);
+        }
+
+        let items = self.unlock(items).await?;
+        let reply = self.call(&object_path(SERVICE_PATH)?, SERVICE_INTERFACE, "GetSecrets",
+                              (items.clone(), self.session.clone()).to_variant(), "(a{o(oayays)})").await?;
+
+        let mut secrets = reply.child_value(0)
+            .get::<HashMap<ObjectPath, (ObjectPath, Vec<u8>, Vec<u8>, String)>>()
+            .ok_or_else(|| invalid_reply("GetSecrets"))?;
+
+        // Keep the order of the search, in case there are several items
+        let password = items.iter()
+            .find_map(|item| secrets.get(item))
+            .map(|(_, _, value, _)| SambaPassword::new(String::from_utf8_lossy(value).into_owned()));
+
+        for (_, _, value, _) in secrets.values_mut() {
+            value.zeroize();
+        }
+
+        Ok(password)
+    }
+
+    // Forget the password for `server` and `domain`
+    pub async fn delete(&self, server: &str, domain: &str) -> Result<(), glib::Error> {
+        let items = self.search(server, domain).await?;
+        if items.is_empty() {
+            return Ok(());
+        }
+
+        for item in self.unlock(items).await? {
+            let reply = self.call(&item, ITEM_INTERFACE, "Delete", ().to_variant(), "(o)").await?;
+            let prompt = reply.child_value(0).get::<ObjectPath>()
+                .ok_or_else(|| invalid_reply("Delete"))?;
+            self.prompt(&prompt).await?;
+        }
+
+        Ok(())
+    }
+
+    // Items of `server` and `domain`, locked or not
+    async fn search(&self, server: &str, domain: &str) -> Result<Vec<ObjectPath>, glib::Error> {
+        let reply = self.call(&object_path(SERVICE_PATH)?, SERVICE_INTERFACE, "SearchItems",
+                              (attributes(server, domain),).to_variant(), "(aoao)").await?;
+
+        let (mut unlocked, locked) = reply.get::<(Vec<ObjectPath>, Vec<ObjectPath>)>()
+            .ok_or_else(|| invalid_reply("SearchItems"))?;
+
+        unlocked.extend(locked);
+        Ok(unlocked)
+    }
+
+    // Unlock items or collections, the keyring may ask the user for its password
+    async fn unlock(&self, objects: Vec<ObjectPath>) -> Result<Vec<ObjectPath>, glib::Error> {
+        let reply = self.call(&object_path(SERVICE_PATH)?, SERVICE_INTERFACE, "Unlock",
+                              (objects,).to_variant(), "(aoo)").await?;
+
+        let (mut unlocked, prompt) = reply.get::<(Vec<ObjectPath>, ObjectPath)>()
+            .ok_or_else(|| invalid_reply("Unlock"))?;
+
+        if let Some(result) = self.prompt(&prompt).await? {
+            unlocked.extend(result.get::<Vec<ObjectPath>>().unwrap_or_default());
+        }
+
+        Ok(unlocked)
+    }
+
+    // Show a prompt of the keyring and wait until the user answered it.
+    // "/" means no prompt is needed. Returns the result of the prompt, an error if it was dismissed.
+    async fn prompt(&self, prompt: &ObjectPath) -> Result<Option<Variant>, glib::Error> {
+        if prompt.as_str() == "/" {
+            return Ok(None);
+        }
+
+        let (tx, rx) = oneshot::channel::<(bool, Variant)>();
+        let tx = Rc::new(RefCell::new(Some(tx)));
+
+        // Subscribe before the prompt is shown, so the Completed signal can't be missed
+        let _subscription = self.connection.subscribe_to_signal(
+            Some(&self.bus_name),
+            Some(PROMPT_INTERFACE),
+            Some("Completed"),
+            Some(prompt.as_str()),
+            None,
+            DBusSignalFlags::NONE,
+            move |signal| {
+                if let (Some(sender), Some((dismissed, result))) = (tx.borrow_mut().take(), signal.parameters.get::<(bool, Variant)>()) {
+                    let _ = sender.send((dismissed, result));
+                }
+            },
+        );
+
+        self.call(prompt, PROMPT_INTERFACE, "Prompt", ("",).to_variant(), "()").await?;
+
+        let (dismissed, result) = rx.await
+            .map_err(|_| glib::Error::new(IOErrorEnum::Closed, "The keyring prompt was closed"))?;
+
+        if dismissed {
+            return Err(glib::Error::new(IOErrorEnum::Cancelled, "The keyring prompt was dismissed"));
+        }
+
+        Ok(Some(result.as_variant().unwrap_or(result)))
+    }
+
+    async fn call(&self, path: &ObjectPath, interface: &str, method: &str, parameters: Variant, reply_type: &str) -> Result<Variant, glib::Error> {
+        self.connection.call_future(
+            Some(&self.bus_name),
+            path.as_str(),
+            interface,
+            method,
+            Some(&parameters),
+            Some(VariantTy::new(reply_type).unwrap()),
+            DBusCallFlags::NONE,
+            CALL_TIMEOUT_MS,
+        ).await
+    }
+}
+
+// Attributes that identify the password of a server and domain
+fn attributes(server: &str, domain: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    attributes.insert("application".to_string(), APPLICATION.to_string());
+    attributes.insert("server".to_string(), server.to_lowercase());
+    attributes.insert("domain".to_string(), domain.to_lowercase());
+    attributes
+}
+
+fn object_path(path: &str) -> Result<ObjectPath, glib::Error> {
+    ObjectPath::try_from(path)
+        .map_err(|_| glib::Error::new(IOErrorEnum::InvalidArgument, &format!("Invalid object path {}", path)))
+}
+
+fn invalid_reply(method: &str) -> glib::Error {
+    glib::Error::new(IOErrorEnum::InvalidData, &format!("Unexpected reply to {} from the Secret Service", method))
+}
@@ -0,0 +1,62 @@
+use std::path::Path;
+use zeroize::Zeroizing;
+use super::{Result, SambaAuthMode, SambaCredentials, SambaError, SambaPassword};
+
+// Parse an authentication file as used by `smbclient -A` and `mount.cifs credentials=`:
+//
+//   username = alice
+//   password = secret
+//   domain   = CORP
+//
+// mount.cifs also accepts the short keys user, pass and dom, and the domain may be part of the
+// username (CORP\alice or CORP/alice). Empty lines and lines starting with # or ; are ignored.
+pub fn parse_credentials_file(content: &str) -> Result<SambaCredentials> {
+    let mut username = None;
+    let mut password = None;
+    let mut domain = None;
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| SambaError::InvalidCredentialsFile(format!("line {} has no '='", number + 1)))?;
+        let value = value.trim().to_string();
+
+        match key.trim().to_lowercase().as_str() {
+            "username" | "user" => username = Some(value),
+            "password" | "pass" => password = Some(SambaPassword::new(value)),
+            "domain" | "dom" | "workgroup" => domain = Some(value),
+            // Other keys of mount.cifs (e.g. uid) don't matter here
+            _ => {}
+        }
+    }
+
+    let mut username = username
+        .filter(|username| !username.is_empty())
+        .ok_or_else(|| SambaError::InvalidCredentialsFile("no username".to_string()))?;
+
+    // CORP\alice or CORP/alice, an explicit domain line wins
+    if let Some((user_domain, user)) = username.clone().split_once(['\\', '/']) {
+        domain = domain.or_else(|| Some(user_domain.to_string()));
+        username = user.to_string();
+    }
+
+    Ok(SambaCredentials {
+        workgroup: domain.unwrap_or_default(),
+        username,
+        password: password.unwrap_or_default(),
+        auth_mode: SambaAuthMode::Password,
+    })
+}
+
+pub fn read_credentials_file(path: &Path) -> Result<SambaCredentials> {
+    // The content holds the password as well
+    let content = std::fs::read_to_string(path)
+        .map(Zeroizing::new)
+        .map_err(|e| SambaError::InvalidCredentialsFile(format!("{}: {}", path.display(), e)))?;
+
+    parse_credentials_file(&content)
+}
@@ -0,0 +1,89 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+pub type Result<T> = std::result::Result<T, SambaError>;
+
+// Errors reported by libsmbclient, derived from errno after a failed call
+#[derive(Debug)]
+pub enum SambaError {
+    AccessDenied,
+    NotFound,
+    HostUnreachable,
+    ConnectionRefused,
+    Timeout,
+    LogonFailure,
+    ProtocolNegotiation,
+    // The path can't be passed to libsmbclient (e.g. contains a NUL byte)
+    InvalidPath(String),
+    // The worker thread of the connection is gone
+    WorkerStopped,
+    // The request was cancelled by the user
+    Cancelled,
+    // A credentials file (smbclient -A) can't be read or has no username
+    InvalidCredentialsFile(String),
+    // The minimum protocol is newer than the maximum protocol, labels of both
+    InvalidProtocolRange(&'static str, &'static str),
+    // Every errno without a better match
+    Other(io::Error),
+}
+
+impl SambaError {
+    pub fn from_errno(errno: i32) -> Self {
+        match errno {
+            libc::EACCES => SambaError::AccessDenied,
+            // libsmbclient reports a rejected logon (NT_STATUS_LOGON_FAILURE) as EPERM
+            libc::EPERM => SambaError::LogonFailure,
+            libc::ENOENT | libc::ENODEV | libc::ENXIO | libc::ENOTDIR => SambaError::NotFound,
+            libc::EHOSTUNREACH | libc::ENETUNREACH | libc::EHOSTDOWN | libc::ENETDOWN => SambaError::HostUnreachable,
+            libc::ECONNREFUSED => SambaError::ConnectionRefused,
+            libc::ETIMEDOUT | libc::ETIME => SambaError::Timeout,
+            libc::EPROTONOSUPPORT | libc::EPROTO | libc::EOPNOTSUPP | libc::ECONNRESET => SambaError::ProtocolNegotiation,
+            _ => SambaError::Other(io::Error::from_raw_os_error(errno)),
+        }
+    }
+
+    // Build the error from the errno of the last failed libsmbclient call
+    pub fn last_os_error() -> Self {
+        io::Error::last_os_error().into()
+    }
+}
+
+impl Display for SambaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SambaError::AccessDenied => write!(f, "Access denied. Your account has no permission to open this location."),
+            SambaError::NotFound => write!(f, "The server, share or folder does not exist."),
+            SambaError::HostUnreachable => write!(f, "The server can't be reached. Check the address and your network connection."),
+            SambaError::ConnectionRefused => write!(f, "The server refused the connection. Is file and printer sharing enabled?"),
+            SambaError::Timeout => write!(f, "The server did not answer in time."),
+            SambaError::LogonFailure => write!(f, "Logon failed. Check username, password and domain."),
+            SambaError::ProtocolNegotiation => write!(f, "No common SMB protocol version could be negotiated with the server."),
+            SambaError::InvalidPath(path) => write!(f, "Invalid SMB path: {}", path),
+            SambaError::WorkerStopped => write!(f, "The connection to the server was closed."),
+            SambaError::Cancelled => write!(f, "The operation was cancelled."),
+            SambaError::InvalidCredentialsFile(reason) => write!(f, "Invalid credentials file: {}", reason),
+            SambaError::InvalidProtocolRange(min, max) => {
+                write!(f, "The minimum protocol {} is newer than the maximum protocol {}.", min, max)
+            }
+            SambaError::Other(e) => write!(f, "SMB error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SambaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SambaError::Other(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SambaError {
+    fn from(e: io::Error) -> Self {
+        match e.raw_os_error() {
+            Some(errno) => SambaError::from_errno(errno),
+            None => SambaError::Other(e),
+        }
+    }
+}
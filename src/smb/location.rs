@@ -0,0 +1,116 @@
+use url::Url;
+use super::{Result, SambaError};
+
+// A location typed by the user, normalized to a smb:// URL.
+// User and domain given in the location are split off, the URL itself never contains credentials.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SambaLocation {
+    pub url: Url,
+    pub workgroup: Option<String>,
+    pub username: Option<String>,
+}
+
+// Parse the different ways a SMB location is written down:
+//  - smb://server/share, smb://DOMAIN;user@server:445/share
+//  - \\server\share (UNC path) and //server/share
+//  - server/share, server
+//  - IPv6 literals, with or without brackets: [fe80::1]/share, \\fe80::1\share
+// An empty input is the network root smb://
+pub fn parse_location(input: &str) -> Result<SambaLocation> {
+    let input = input.trim();
+    let invalid = || SambaError::InvalidPath(input.to_string());
+
+    // Strip the scheme or the UNC prefix
+    let (rest, is_url) = if input.get(..6).is_some_and(|s| s.eq_ignore_ascii_case("smb://")) {
+        (&input[6..], true)
+    } else if input.contains("://") {
+        return Err(invalid());
+    } else if let Some(rest) = input.strip_prefix("\\\\").or_else(|| input.strip_prefix("//")) {
+        (rest, false)
+    } else {
+        (input, false)
+    };
+
+    // Authority ends at the first separator. UNC paths use backslashes, but a
+    // DOMAIN\user in front of the @ must not be taken as a separator.
+    let at = rest.find('@');
+    let authority_end = rest
+        .char_indices()
+        .find(|(i, c)| *c == '/' || (*c == '\\' && at.is_none_or(|at| *i > at)))
+        .map(|(i, _)| i)
+        .unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(authority_end);
+
+    // Userinfo: DOMAIN;user, DOMAIN\user or user, the password is dropped
+    let (userinfo, host_port) = match authority.rfind('@') {
+        Some(i) => (Some(&authority[..i]), &authority[i + 1..]),
+        None => (None, authority),
+    };
+
+    let (workgroup, username) = match userinfo {
+        Some(userinfo) => {
+            let userinfo = if is_url { url_escape::decode(userinfo).to_string() } else { userinfo.to_string() };
+            let user = userinfo.split(':').next().unwrap_or_default();
+            match user.split_once(';').or_else(|| user.split_once('\\')) {
+                Some((domain, user)) => (non_empty(domain), non_empty(user)),
+                None => (None, non_empty(user)),
+            }
+        }
+        None => (None, None),
+    };
+
+    // Host with optional port. IPv6 literals are kept in brackets.
+    let (host, port) = if let Some(bracketed) = host_port.strip_prefix('[') {
+        let end = bracketed.find(']').ok_or_else(invalid)?;
+        let port = bracketed[end + 1..].strip_prefix(':');
+        (format!("[{}]", &bracketed[..end]), port)
+    } else if host_port.matches(':').count() > 1 {
+        (format!("[{}]", host_port), None)
+    } else {
+        match host_port.split_once(':') {
+            Some((host, port)) => (host.to_string(), Some(port)),
+            None => (host_port.to_string(), None),
+        }
+    };
+
+    let port = match port {
+        Some(port) => Some(port.parse::<u16>().map_err(|_| invalid())?),
+        None => None,
+    };
+
+    if host.is_empty() {
+        if path.trim_matches(|c| c == '/' || c == '\\').is_empty() && port.is_none() {
+            let url = Url::parse("smb://").map_err(|_| invalid())?;
+            return Ok(SambaLocation { url, workgroup, username });
+        }
+        return Err(invalid());
+    }
+
+    let host = if host.starts_with('[') { host } else { url_escape::encode_component(&host).to_string() };
+    let mut url = Url::parse(&format!("smb://{}/", host)).map_err(|_| invalid())?;
+    url.set_port(port).map_err(|_| invalid())?;
+
+    // Segments of a smb:// URL are already percent-encoded, UNC paths are taken literally
+    {
+        let mut segments = url.path_segments_mut().map_err(|_| invalid())?;
+        segments.clear();
+        for segment in path.split(|c| c == '/' || (c == '\\' && !is_url)).filter(|s| !s.is_empty()) {
+            if !is_url {
+                segments.push(segment);
+            } else {
+                segments.push(&url_escape::decode(segment));
+            }
+        }
+    }
+
+    // A bare server is a directory and ends with a slash
+    if url.path().is_empty() {
+        url.set_path("/");
+    }
+
+    Ok(SambaLocation { url, workgroup, username })
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    if s.is_empty() { None } else { Some(s.to_string()) }
+}
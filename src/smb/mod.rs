@@ -0,0 +1,502 @@
+use std::cmp::Ord;
+use std::collections::HashMap;
+use std::default::Default;
+use std::ffi::{c_void, CStr, CString};
+use std::prelude::v1::{Err, Ok};
+use std::{ptr, slice};
+use std::string::{String, ToString};
+use std::sync::{Arc, Mutex, Once};
+use std::vec::Vec;
+use libc::{c_char, c_int};
+use url::Url;
+
+mod credentials_file;
+mod error;
+mod location;
+mod options;
+mod password;
+mod worker;
+
+pub use credentials_file::{parse_credentials_file, read_credentials_file};
+pub use error::{Result, SambaError};
+pub use location::{parse_location, SambaLocation};
+pub use options::{SambaConnectionOptions, SambaEncryption, SambaProtocol, SambaSigning};
+pub use password::SambaPassword;
+pub use worker::SambaWorker;
+
+#[repr(C)]
+pub struct SMBCCTX {
+    _private: [i32; 0],
+}
+
+#[repr(C)]
+pub struct SMBCFILE {
+    _private: [u8; 0],
+}
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmbcType {
+    SmbcWorkgroup = 1,
+    SmbcServer = 2,
+    SmbcFileShare = 3,
+    SmbcPrinterShare = 4,
+    SmbcCommsShare = 5,
+    SmbcIpcShare = 6,
+    SmbcDir = 7,
+    SmbcFile = 8,
+    SmbcLink = 9,
+}
+
+#[repr(C)]
+pub struct SmbcDirent {
+    pub smbc_type: SmbcType,
+    pub dirlen: u32,
+    pub commentlen: u32,
+    pub comment: *mut i8,
+    pub namelen: u32,
+    pub name: [i8; 1],
+}
+
+
+extern "C" {
+    fn smbc_thread_posix();
+    fn smbc_new_context() -> *mut SMBCCTX;
+    fn smbc_init_context(ctx: *mut SMBCCTX) -> *mut SMBCCTX;
+    fn smbc_free_context(ctx: *mut SMBCCTX, shutdown_ctx: c_int);
+    fn smbc_setFunctionAuthDataWithContext(ctx: *mut SMBCCTX, func: SmbcAuthFnWithContext);
+    fn smbc_setOptionUserData(ctx: *mut SMBCCTX, user_data: *mut c_void);
+    fn smbc_setOptionUseKerberos(ctx: *mut SMBCCTX, b: c_int);
+    fn smbc_setOptionFallbackAfterKerberos(ctx: *mut SMBCCTX, b: c_int);
+    fn smbc_setOptionUseCCache(ctx: *mut SMBCCTX, b: c_int);
+    fn smbc_setOptionNoAutoAnonymousLogin(ctx: *mut SMBCCTX, b: c_int);
+    fn smbc_setOptionProtocols(ctx: *mut SMBCCTX, min_proto: *const c_char, max_proto: *const c_char) -> c_int;
+    fn smbc_setOptionSmbEncryptionLevel(ctx: *mut SMBCCTX, level: c_int);
+    fn smbc_setPort(ctx: *mut SMBCCTX, port: u16);
+    fn smbc_setTimeout(ctx: *mut SMBCCTX, timeout: c_int);
+    fn smbc_setConfiguration(ctx: *mut SMBCCTX, file: *const c_char) -> c_int;
+    fn smbc_getOptionUserData(ctx: *mut SMBCCTX) -> *mut c_void;
+    fn smbc_getFunctionOpendir(ctx: *mut SMBCCTX) -> Option<SmbcOpendirFn>;
+    fn smbc_getFunctionReaddir(ctx: *mut SMBCCTX) -> Option<SmbcReaddirFn>;
+    fn smbc_getFunctionClosedir(ctx: *mut SMBCCTX) -> Option<SmbcClosedirFn>;
+    fn smbc_getFunctionStat(ctx: *mut SMBCCTX) -> Option<SmbcStatFn>;
+}
+
+// Function pointer types of the context based libsmbclient API.
+// The context based functions are used instead of the compat API (smbc_opendir, ...),
+// because the compat API only knows one global context per process.
+type SmbcAuthFnWithContext = unsafe extern "C" fn(
+    *mut SMBCCTX,
+    *const c_char, *const c_char,
+    *mut c_char, c_int,
+    *mut c_char, c_int,
+    *mut c_char, c_int,
+);
+type SmbcOpendirFn = unsafe extern "C" fn(*mut SMBCCTX, *const c_char) -> *mut SMBCFILE;
+type SmbcReaddirFn = unsafe extern "C" fn(*mut SMBCCTX, *mut SMBCFILE) -> *mut SmbcDirent;
+type SmbcClosedirFn = unsafe extern "C" fn(*mut SMBCCTX, *mut SMBCFILE) -> c_int;
+type SmbcStatFn = unsafe extern "C" fn(*mut SMBCCTX, *const c_char, *mut libc::stat) -> c_int;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SambaAuthMode {
+    // Username and password (NTLM, or Kerberos if libsmbclient decides so)
+    #[default]
+    Password,
+    // Kerberos only, with the ticket from the credential cache of the user (kinit)
+    Kerberos,
+    // Guest account, falls back to an anonymous login if the server has no guest account
+    Guest,
+}
+
+impl SambaAuthMode {
+    pub const ALL: [SambaAuthMode; 3] = [
+        SambaAuthMode::Password,
+        SambaAuthMode::Kerberos,
+        SambaAuthMode::Guest,
+    ];
+
+    // Name used in the config file
+    pub fn key(&self) -> &'static str {
+        match self {
+            SambaAuthMode::Password => "password",
+            SambaAuthMode::Kerberos => "kerberos",
+            SambaAuthMode::Guest => "guest",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        SambaAuthMode::ALL.iter().copied().find(|mode| mode.key() == key)
+    }
+}
+
+// Not Clone on purpose: a connection shares its credentials through an Arc, so there is
+// only one copy of the password, which is wiped once the last user drops it.
+#[derive(Debug)]
+pub struct SambaCredentials {
+    pub workgroup: String,
+    pub username: String,
+    // Not needed for SambaAuthMode::Kerberos and SambaAuthMode::Guest
+    pub password: SambaPassword,
+    pub auth_mode: SambaAuthMode,
+}
+
+// Credentials attached to one SMB context.
+// libsmbclient asks for credentials per server and share, so specific credentials
+// can be registered for a server or a single share. Everything else falls back to the default.
+struct ContextAuth {
+    default: Arc<SambaCredentials>,
+    // Key: (lowercase server, lowercase share), an empty share matches the whole server
+    specific: HashMap<(String, String), Arc<SambaCredentials>>,
+}
+
+impl ContextAuth {
+    fn lookup(&self, server: &str, share: &str) -> &SambaCredentials {
+        let server = server.to_lowercase();
+        let share = share.to_lowercase();
+
+        self.specific.get(&(server.clone(), share))
+            .or_else(|| self.specific.get(&(server, String::new())))
+            .unwrap_or(&self.default)
+    }
+}
+
+// Owns the libsmbclient context, which is freed when the connection is dropped
+pub struct SambaConnection {
+    ctx: *mut SMBCCTX,
+    pub credentials: Arc<SambaCredentials>,
+    pub server_root: String,
+    pub options: SambaConnectionOptions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SambaEntryType {
+    Workgroup,
+    Server,
+    File,
+    Directory,
+    Printer,
+    Unknown,
+}
+
+impl SambaEntryType {
+    // Entries that can be listed like a directory
+    pub fn is_browsable(&self) -> bool {
+        matches!(self, SambaEntryType::Workgroup | SambaEntryType::Server | SambaEntryType::Directory)
+    }
+}
+
+impl Default for SambaEntryType {
+    fn default() -> Self {
+        SambaEntryType::Unknown
+    }
+}
+
+pub struct SambaDirectoryEntry {
+    pub name: String,
+    pub entry_type: SambaEntryType,
+    // Comment of shares and servers, print servers often put the model and location in here
+    pub comment: String,
+}
+
+impl SambaDirectoryEntry {
+    // Build the URL of this entry from the URL of the directory it was listed in.
+    // Workgroups and servers are listed from smb:// and smb://WORKGROUP/ but are addressed as smb://NAME/.
+    pub fn url(&self, parent: &Url) -> Option<Url> {
+        match self.entry_type {
+            SambaEntryType::Workgroup | SambaEntryType::Server => {
+                let name = url_escape::encode_component(&self.name);
+                Url::parse(&format!("smb://{}/", name)).ok()
+            }
+            _ => {
+                let mut url = parent.clone();
+                {
+                    let mut segments = url.path_segments_mut().ok()?;
+                    segments.pop_if_empty().push(&self.name);
+
+                    // Directories end with a slash, so that relative joins stay inside of them
+                    if self.entry_type.is_browsable() {
+                        segments.push("");
+                    }
+                }
+                Some(url)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SambaStat {
+    pub entry_type: SambaEntryType,
+    // Size in bytes
+    pub size: u64,
+    // Last modification as unix timestamp
+    pub modified: i64,
+}
+
+// URL of the directory that contains `url`.
+// Shares and folders go up to their parent, servers go up to the network root (smb://).
+pub fn parent_url(url: &Url) -> Option<Url> {
+    let mut segments: Vec<&str> = url.path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    if segments.pop().is_some() {
+        let mut parent = url.clone();
+        parent.set_path(&format!("/{}", segments.iter().map(|s| format!("{}/", s)).collect::<String>()));
+        return Some(parent);
+    }
+
+    if url.host_str().is_some_and(|h| !h.is_empty()) {
+        return Url::parse("smb://").ok();
+    }
+
+    None
+}
+
+static THREAD_INIT: Once = Once::new();
+
+// Protocols, signing and the loaded smb.conf are global state of libsmbclient. A cancelled worker
+// may still be connecting on its old thread while the new one is set up, so only one context
+// at a time is created, configured or freed.
+static CONTEXT_SETUP: Mutex<()> = Mutex::new(());
+
+// A safe wrapper around the SMB client library.
+// Every connection has its own context and credentials, so several connections can be used side by side.
+// A connection can be moved to another thread, but must only be used by one thread at a time.
+// Use SambaWorker to access a connection from the GTK main loop.
+impl SambaConnection {
+    pub fn connect(credentials: Arc<SambaCredentials>, server_root: &str, options: SambaConnectionOptions) -> Result<Self> {
+        options.validate()?;
+
+        // Contexts are used from worker threads, libsmbclient must know before it is used at all
+        THREAD_INIT.call_once(|| unsafe { smbc_thread_posix() });
+        let _setup = CONTEXT_SETUP.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let server_root = server_root.to_string();
+        unsafe {
+            let ctx = smbc_new_context();
+            if ctx.is_null() {
+                return Err(SambaError::last_os_error());
+            }
+
+            // Attach the credentials to this context, the auth callback looks them up from there
+            let auth = Box::new(ContextAuth {
+                default: credentials.clone(),
+                specific: HashMap::new(),
+            });
+            smbc_setOptionUserData(ctx, Box::into_raw(auth) as *mut c_void);
+            smbc_setFunctionAuthDataWithContext(ctx, auth_fn);
+
+            // Kerberos uses the credential cache of the user and must not fall back to NTLM,
+            // there is no password that could be sent instead
+            if credentials.auth_mode == SambaAuthMode::Kerberos {
+                smbc_setOptionUseKerberos(ctx, 1);
+                smbc_setOptionFallbackAfterKerberos(ctx, 0);
+                smbc_setOptionUseCCache(ctx, 1);
+            }
+
+            // Only guests may be retried anonymously if the login is refused, a wrong password
+            // or a missing Kerberos ticket must fail instead of silently browsing as anonymous
+            let no_anonymous = match credentials.auth_mode {
+                SambaAuthMode::Guest => 0,
+                SambaAuthMode::Password | SambaAuthMode::Kerberos => 1,
+            };
+            smbc_setOptionNoAutoAnonymousLogin(ctx, no_anonymous);
+
+            if let Err(error) = options.apply_to_context(ctx) {
+                free_context_auth(ctx);
+                smbc_free_context(ctx, 0);
+                return Err(error);
+            }
+
+            if smbc_init_context(ctx).is_null() {
+                let error = SambaError::last_os_error();
+                free_context_auth(ctx);
+                smbc_free_context(ctx, 0);
+                return Err(error);
+            }
+
+            if let Err(error) = options.apply_signing(ctx) {
+                free_context_auth(ctx);
+                smbc_free_context(ctx, 1);
+                return Err(error);
+            }
+
+            Ok(SambaConnection { ctx, credentials, server_root, options })
+        }
+    }
+
+    // Register credentials for a specific server, or for a single share if `share` is not empty.
+    // They are used instead of the default credentials of this connection.
+    // Must be called on the thread using the connection, auth_fn reads the map without locking.
+    pub fn add_credentials(&self, server: &str, share: &str, credentials: Arc<SambaCredentials>) {
+        unsafe {
+            let auth = smbc_getOptionUserData(self.ctx) as *mut ContextAuth;
+            if let Some(auth) = auth.as_mut() {
+                auth.specific.insert((server.to_lowercase(), share.to_lowercase()), credentials);
+            }
+        }
+    }
+
+    pub fn stat(&self, path: &Url) -> Result<SambaStat> {
+        unsafe {
+            let stat_fn = smbc_getFunctionStat(self.ctx)
+                .ok_or_else(|| SambaError::Other(std::io::Error::other("SMB context is missing the stat function")))?;
+
+            let c_path = CString::new(path.as_str())
+                .map_err(|_| SambaError::InvalidPath(path.to_string()))?;
+
+            let mut st: libc::stat = std::mem::zeroed();
+            if stat_fn(self.ctx, c_path.as_ptr(), &mut st) < 0 {
+                return Err(SambaError::last_os_error());
+            }
+
+            let entry_type = match st.st_mode & libc::S_IFMT {
+                libc::S_IFDIR => SambaEntryType::Directory,
+                libc::S_IFREG => SambaEntryType::File,
+                _ => SambaEntryType::Unknown,
+            };
+
+            Ok(SambaStat {
+                entry_type,
+                size: st.st_size as u64,
+                modified: st.st_mtime as i64,
+            })
+        }
+    }
+
+    pub fn list_directory(&self, path: &Url) -> Result<Vec<SambaDirectoryEntry>> {
+        let mut entries: Vec<SambaDirectoryEntry> = Vec::new();
+
+        unsafe {
+            let (opendir, readdir, closedir) = match (
+                smbc_getFunctionOpendir(self.ctx),
+                smbc_getFunctionReaddir(self.ctx),
+                smbc_getFunctionClosedir(self.ctx),
+            ) {
+                (Some(o), Some(r), Some(c)) => (o, r, c),
+                _ => return Err(SambaError::Other(std::io::Error::other("SMB context is missing directory functions"))),
+            };
+
+            // convert Rust &str to a null-terminated C string
+            let c_path = CString::new(path.as_str())
+                .map_err(|_| SambaError::InvalidPath(path.to_string()))?;
+
+            let dir = opendir(self.ctx, c_path.as_ptr());
+            if dir.is_null() {
+                return Err(SambaError::last_os_error());
+            }
+
+            loop {
+                let entry = readdir(self.ctx, dir);
+
+                if entry.is_null() {
+                    break;
+                }
+
+                let name_ptr = (*entry).name.as_ptr();
+                let name_len = (*entry).namelen as usize;
+                let name_slice = slice::from_raw_parts(name_ptr as *const u8, name_len);
+                let name = String::from_utf8_lossy(name_slice).to_string();
+
+                let comment_ptr = (*entry).comment;
+                let comment = if comment_ptr.is_null() || (*entry).commentlen == 0 {
+                    String::new()
+                } else {
+                    CStr::from_ptr(comment_ptr).to_string_lossy().trim().to_string()
+                };
+
+                let entry_type = match (*entry).smbc_type {
+                    SmbcType::SmbcWorkgroup => SambaEntryType::Workgroup,
+                    SmbcType::SmbcServer => SambaEntryType::Server,
+                    SmbcType::SmbcDir => SambaEntryType::Directory,
+                    SmbcType::SmbcFileShare => SambaEntryType::Directory,
+                    SmbcType::SmbcFile => SambaEntryType::File,
+                    SmbcType::SmbcPrinterShare => SambaEntryType::Printer,
+                    _ => SambaEntryType::Unknown,
+                };
+
+                entries.push(SambaDirectoryEntry {
+                    name,
+                    entry_type,
+                    comment,
+                });
+            }
+
+            closedir(self.ctx, dir);
+        }
+
+        Ok(entries)
+    }
+}
+
+impl Drop for SambaConnection {
+    fn drop(&mut self) {
+        let _setup = CONTEXT_SETUP.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        unsafe {
+            free_context_auth(self.ctx);
+            smbc_free_context(self.ctx, 1);
+        }
+    }
+}
+
+// The context is owned by exactly one connection and is not tied to the thread that created it
+unsafe impl Send for SambaConnection {}
+
+// Frees the credentials attached to the context, if there are any
+unsafe fn free_context_auth(ctx: *mut SMBCCTX) {
+    let auth = smbc_getOptionUserData(ctx) as *mut ContextAuth;
+    if !auth.is_null() {
+        smbc_setOptionUserData(ctx, ptr::null_mut());
+        drop(Box::from_raw(auth));
+    }
+}
+
+unsafe extern "C" fn auth_fn(
+    ctx: *mut SMBCCTX,
+    server: *const c_char,
+    share: *const c_char,
+    workgroup: *mut c_char, wglen: c_int,
+    username: *mut c_char, unlen: c_int,
+    password: *mut c_char, pwlen: c_int,
+) {
+    unsafe fn write_c_string(dst: *mut c_char, len: c_int, src: &str) {
+        if dst.is_null() || len <= 0 {
+            return;
+        }
+
+        let bytes = src.as_bytes();
+        let max = (len as usize).saturating_sub(1);
+        let n = bytes.len().min(max);
+
+        ptr::copy_nonoverlapping(bytes.as_ptr(), dst as *mut u8, n);
+        *dst.add(n) = 0; // null-terminate
+    }
+
+    unsafe fn read_c_string(src: *const c_char) -> String {
+        if src.is_null() {
+            return String::new();
+        }
+        CStr::from_ptr(src).to_string_lossy().into_owned()
+    }
+
+    // Get the credentials attached to the calling context.
+    // The context is only used by one thread at a time, so no locking is needed here.
+    let auth = smbc_getOptionUserData(ctx) as *const ContextAuth;
+    let auth = match auth.as_ref() {
+        Some(auth) => auth,
+        None => return,
+    };
+
+    let credentials = auth.lookup(&read_c_string(server), &read_c_string(share));
+
+    // Guests log in as "guest" without password, whatever was typed in before
+    let (user, pass) = match credentials.auth_mode {
+        SambaAuthMode::Guest => ("guest", ""),
+        _ => (credentials.username.as_str(), credentials.password.as_str()),
+    };
+
+    write_c_string(workgroup, wglen, credentials.workgroup.as_str());
+    write_c_string(username,  unlen, user);
+    write_c_string(password,  pwlen, pass);
+}
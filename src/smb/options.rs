@@ -0,0 +1,315 @@
+use std::ffi::CString;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use libc::c_int;
+use super::{smbc_setConfiguration, smbc_setOptionProtocols, smbc_setOptionSmbEncryptionLevel, smbc_setPort, smbc_setTimeout, Result, SambaError, SMBCCTX};
+
+// SMB dialects that can be used as lower or upper limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SambaProtocol {
+    // Whatever libsmbclient and smb.conf use
+    #[default]
+    Default,
+    // SMB1, only for legacy servers
+    Nt1,
+    Smb2,
+    Smb3,
+}
+
+impl SambaProtocol {
+    pub const ALL: [SambaProtocol; 4] = [
+        SambaProtocol::Default,
+        SambaProtocol::Nt1,
+        SambaProtocol::Smb2,
+        SambaProtocol::Smb3,
+    ];
+
+    // Name used in the config file and by smb.conf ("client min protocol")
+    pub fn key(&self) -> &'static str {
+        match self {
+            SambaProtocol::Default => "default",
+            SambaProtocol::Nt1 => "NT1",
+            SambaProtocol::Smb2 => "SMB2",
+            SambaProtocol::Smb3 => "SMB3",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        SambaProtocol::ALL.iter().copied().find(|protocol| protocol.key() == key)
+    }
+
+    // Order of the dialects, None for Default which has no fixed version
+    fn version(&self) -> Option<u8> {
+        match self {
+            SambaProtocol::Default => None,
+            SambaProtocol::Nt1 => Some(1),
+            SambaProtocol::Smb2 => Some(2),
+            SambaProtocol::Smb3 => Some(3),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SambaProtocol::Default => "Default",
+            SambaProtocol::Nt1 => "SMB1 (NT1)",
+            SambaProtocol::Smb2 => "SMB2",
+            SambaProtocol::Smb3 => "SMB3",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SambaSigning {
+    #[default]
+    Default,
+    Off,
+    Desired,
+    Required,
+}
+
+impl SambaSigning {
+    pub const ALL: [SambaSigning; 4] = [
+        SambaSigning::Default,
+        SambaSigning::Off,
+        SambaSigning::Desired,
+        SambaSigning::Required,
+    ];
+
+    // Name used in the config file
+    pub fn key(&self) -> &'static str {
+        match self {
+            SambaSigning::Default => "default",
+            SambaSigning::Off => "off",
+            SambaSigning::Desired => "desired",
+            SambaSigning::Required => "required",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        SambaSigning::ALL.iter().copied().find(|signing| signing.key() == key)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SambaSigning::Default => "Default",
+            SambaSigning::Off => "Off",
+            SambaSigning::Desired => "Desired",
+            SambaSigning::Required => "Required",
+        }
+    }
+
+    // Value of "client signing" in smb.conf
+    fn smb_conf_value(&self) -> &'static str {
+        match self {
+            SambaSigning::Default => "default",
+            SambaSigning::Off => "disabled",
+            SambaSigning::Desired => "desired",
+            SambaSigning::Required => "required",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SambaEncryption {
+    #[default]
+    Default,
+    Off,
+    // Encrypt if the server supports it
+    Request,
+    // Fail if the server can't encrypt
+    Require,
+}
+
+impl SambaEncryption {
+    pub const ALL: [SambaEncryption; 4] = [
+        SambaEncryption::Default,
+        SambaEncryption::Off,
+        SambaEncryption::Request,
+        SambaEncryption::Require,
+    ];
+
+    // Name used in the config file
+    pub fn key(&self) -> &'static str {
+        match self {
+            SambaEncryption::Default => "default",
+            SambaEncryption::Off => "off",
+            SambaEncryption::Request => "request",
+            SambaEncryption::Require => "require",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        SambaEncryption::ALL.iter().copied().find(|encryption| encryption.key() == key)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SambaEncryption::Default => "Default",
+            SambaEncryption::Off => "Off",
+            SambaEncryption::Request => "If supported",
+            SambaEncryption::Require => "Required",
+        }
+    }
+
+    // smbc_smb_encrypt_level of libsmbclient.h
+    fn level(&self) -> c_int {
+        match self {
+            SambaEncryption::Default => -1,
+            SambaEncryption::Off => 0,
+            SambaEncryption::Request => 1,
+            SambaEncryption::Require => 2,
+        }
+    }
+}
+
+// Connection settings that differ from the libsmbclient defaults, e.g. for legacy SMB1 servers
+// or hardened servers that require encryption.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SambaConnectionOptions {
+    pub min_protocol: SambaProtocol,
+    pub max_protocol: SambaProtocol,
+    pub signing: SambaSigning,
+    pub encryption: SambaEncryption,
+    // 0 tries the default ports 445 and 139
+    pub port: u16,
+    // Timeout of a single SMB call in milliseconds, 0 keeps the libsmbclient default (20 s)
+    pub timeout_ms: u32,
+}
+
+impl SambaConnectionOptions {
+    // Settings that can't work together, checked before a context is created
+    pub fn validate(&self) -> Result<()> {
+        if let (Some(min), Some(max)) = (self.min_protocol.version(), self.max_protocol.version()) {
+            if min > max {
+                return Err(SambaError::InvalidProtocolRange(self.min_protocol.label(), self.max_protocol.label()));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Settings stored in the context itself, must be applied before smbc_init_context
+    pub(super) unsafe fn apply_to_context(&self, ctx: *mut SMBCCTX) -> Result<()> {
+        smbc_setPort(ctx, self.port);
+        if self.timeout_ms > 0 {
+            smbc_setTimeout(ctx, self.timeout_ms.min(c_int::MAX as u32) as c_int);
+        }
+        smbc_setOptionSmbEncryptionLevel(ctx, self.encryption.level());
+
+        // The protocols are process wide settings of libsmbclient and win over smb.conf once set.
+        // Default leaves them alone, unless a previous connection set a limit. That one is
+        // replaced by the value of the system smb.conf again.
+        let overridden = PROTOCOLS_OVERRIDDEN.load(Ordering::Relaxed);
+        let protocol = |protocol: SambaProtocol, smb_conf_key: &str| match protocol {
+            SambaProtocol::Default if overridden => {
+                let value = system_smb_conf_value(smb_conf_key).unwrap_or_else(|| protocol.key().to_string());
+                CString::new(value).ok()
+            }
+            SambaProtocol::Default => None,
+            _ => CString::new(protocol.key()).ok(),
+        };
+        let min = protocol(self.min_protocol, "client min protocol");
+        let max = protocol(self.max_protocol, "client max protocol");
+
+        if min.is_some() || max.is_some() {
+            let as_ptr = |value: &Option<CString>| value.as_ref().map_or(ptr::null(), |value| value.as_ptr());
+            if smbc_setOptionProtocols(ctx, as_ptr(&min), as_ptr(&max)) == 0 {
+                return Err(SambaError::ProtocolNegotiation);
+            }
+        }
+        PROTOCOLS_OVERRIDDEN.store(
+            self.min_protocol != SambaProtocol::Default || self.max_protocol != SambaProtocol::Default,
+            Ordering::Relaxed,
+        );
+
+        Ok(())
+    }
+
+    // libsmbclient has no setter for signing, it is only read from smb.conf.
+    // A small config with the system smb.conf included is loaded instead, after smbc_init_context
+    // (the first init loads the system smb.conf and would override it). Like the protocols this is process wide.
+    pub(super) unsafe fn apply_signing(&self, ctx: *mut SMBCCTX) -> Result<()> {
+        // Nothing to do as long as no connection changed the signing of the system smb.conf
+        if self.signing == SambaSigning::Default && !SIGNING_OVERRIDDEN.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        SIGNING_OVERRIDDEN.store(self.signing != SambaSigning::Default, Ordering::Relaxed);
+
+        // libsmbclient reads the file right away, it is not needed afterwards
+        let path = write_signing_config(self.signing)?;
+        let result = match CString::new(path.to_string_lossy().as_bytes()) {
+            Ok(c_path) if smbc_setConfiguration(ctx, c_path.as_ptr()) != 0 => Err(SambaError::last_os_error()),
+            Ok(_) => Ok(()),
+            Err(_) => Err(SambaError::InvalidPath(path.display().to_string())),
+        };
+        let _ = std::fs::remove_file(&path);
+
+        result
+    }
+}
+
+const SYSTEM_SMB_CONF: &str = "/etc/samba/smb.conf";
+
+// Set once a connection loaded a config with its own signing setting
+static SIGNING_OVERRIDDEN: AtomicBool = AtomicBool::new(false);
+
+// Set once a connection set its own protocol limits
+static PROTOCOLS_OVERRIDDEN: AtomicBool = AtomicBool::new(false);
+
+// Value of `key` in the [global] section of the system smb.conf, None if it isn't set there.
+// Keys are compared like Samba does, without case and spaces.
+fn system_smb_conf_value(key: &str) -> Option<String> {
+    let normalize = |key: &str| key.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+    let key = normalize(key);
+    let content = std::fs::read_to_string(SYSTEM_SMB_CONF).ok()?;
+
+    let mut global = false;
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') {
+            global = line.eq_ignore_ascii_case("[global]");
+        } else if global && !line.starts_with('#') && !line.starts_with(';') {
+            if let Some((name, value)) = line.split_once('=') {
+                if normalize(name) == key {
+                    return Some(value.trim().to_string());
+                }
+            }
+        }
+    }
+
+    None
+}
+
+// Numbers the config files, so every write gets a file of its own
+static SIGNING_CONFIG_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// The config is written to the runtime directory of the user, which no one else can access.
+// create_new refuses existing files and symlinks, so even in the shared temp directory
+// no other user can put their own settings in place.
+fn write_signing_config(signing: SambaSigning) -> Result<PathBuf> {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(std::env::temp_dir);
+    let path = dir.join(format!(
+        "samba-printer-finder-{}-signing-{}.conf",
+        std::process::id(),
+        SIGNING_CONFIG_COUNTER.fetch_add(1, Ordering::Relaxed),
+    ));
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    writeln!(file, "[global]")?;
+    if Path::new(SYSTEM_SMB_CONF).exists() {
+        writeln!(file, "include = {}", SYSTEM_SMB_CONF)?;
+    }
+    writeln!(file, "client signing = {}", signing.smb_conf_value())?;
+
+    Ok(path)
+}
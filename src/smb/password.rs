@@ -0,0 +1,43 @@
+use std::fmt::{self, Debug, Formatter};
+use zeroize::Zeroizing;
+
+// A password that is wiped from memory when it is dropped.
+// It can't be cloned and its Debug output never contains the password, so it doesn't end up
+// in logs by accident. Use as_str() where the password is really needed.
+#[derive(Default)]
+pub struct SambaPassword(Zeroizing<String>);
+
+impl SambaPassword {
+    pub fn new(password: String) -> Self {
+        SambaPassword(Zeroizing::new(password))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for SambaPassword {
+    fn from(password: String) -> Self {
+        SambaPassword::new(password)
+    }
+}
+
+impl From<&str> for SambaPassword {
+    fn from(password: &str) -> Self {
+        SambaPassword::new(password.to_string())
+    }
+}
+
+impl Debug for SambaPassword {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.is_empty() {
+            true => write!(f, "SambaPassword(\"\")"),
+            false => write!(f, "SambaPassword(<redacted>)"),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+use url::Url;
+use super::{Result, SambaConnection, SambaConnectionOptions, SambaCredentials, SambaDirectoryEntry, SambaError, SambaStat};
+
+// Requests handled by the worker thread, the result is sent back through the oneshot sender
+enum SambaRequest {
+    ListDirectory(Url, oneshot::Sender<Result<Vec<SambaDirectoryEntry>>>),
+    Stat(Url, oneshot::Sender<Result<SambaStat>>),
+    AddCredentials(String, String, Arc<SambaCredentials>),
+}
+
+// Front end for a SambaConnection living on its own thread.
+// All SMB calls block until the server answers, so they must never run on the GTK main loop.
+// The methods only queue a request and can be awaited from MainContext::spawn_local.
+// A request that hangs (e.g. on an unresponsive server) can be cancelled, the worker then
+// continues on a fresh thread and the old one ends once libsmbclient gives up.
+pub struct SambaWorker {
+    requests: RefCell<Sender<SambaRequest>>,
+    // Set when the thread behind `requests` was cancelled
+    cancelled: RefCell<Arc<AtomicBool>>,
+    // Server, share and credentials registered with add_credentials, again given to the connection after a cancel
+    specific: RefCell<Vec<(String, String, Arc<SambaCredentials>)>>,
+    pub credentials: Arc<SambaCredentials>,
+    pub server_root: String,
+    pub options: SambaConnectionOptions,
+}
+
+impl SambaWorker {
+    pub fn connect(credentials: SambaCredentials, server_root: &str, options: SambaConnectionOptions) -> Result<Self> {
+        let credentials = Arc::new(credentials);
+        let (requests, cancelled) = spawn(credentials.clone(), server_root, options)?;
+
+        Ok(SambaWorker {
+            requests: RefCell::new(requests),
+            cancelled: RefCell::new(cancelled),
+            specific: RefCell::new(Vec::new()),
+            credentials,
+            server_root: server_root.to_string(),
+            options,
+        })
+    }
+
+    // Give up on all queued and running requests, they fail with SambaError::Cancelled.
+    // Later requests are handled by a new connection with the same settings.
+    // The old thread may still be blocked in libsmbclient meanwhile, which is safe because
+    // SambaConnection::connect initialises libsmbclient for threads and sets up one context at a time.
+    pub fn cancel(&self) -> Result<()> {
+        let (requests, cancelled) = spawn(self.credentials.clone(), &self.server_root, self.options)?;
+
+        self.cancelled.replace(cancelled).store(true, Ordering::Relaxed);
+        self.requests.replace(requests);
+
+        for (server, share, credentials) in self.specific.borrow().iter() {
+            self.send(SambaRequest::AddCredentials(server.clone(), share.clone(), credentials.clone()))?;
+        }
+
+        Ok(())
+    }
+
+    // Use other credentials for a server, or for a single share if `share` is not empty.
+    // Everything else keeps the credentials of the login.
+    pub fn add_credentials(&self, server: &str, share: &str, credentials: SambaCredentials) -> Result<()> {
+        let credentials = Arc::new(credentials);
+        self.specific.borrow_mut().push((server.to_string(), share.to_string(), credentials.clone()));
+        self.send(SambaRequest::AddCredentials(server.to_string(), share.to_string(), credentials))
+    }
+
+    pub async fn list_directory(&self, path: &Url) -> Result<Vec<SambaDirectoryEntry>> {
+        let (tx, rx) = oneshot::channel();
+        self.send(SambaRequest::ListDirectory(path.clone(), tx))?;
+        rx.await.map_err(|_| SambaError::WorkerStopped)?
+    }
+
+    pub async fn stat(&self, path: &Url) -> Result<SambaStat> {
+        let (tx, rx) = oneshot::channel();
+        self.send(SambaRequest::Stat(path.clone(), tx))?;
+        rx.await.map_err(|_| SambaError::WorkerStopped)?
+    }
+
+    fn send(&self, request: SambaRequest) -> Result<()> {
+        self.requests.borrow().send(request).map_err(|_| SambaError::WorkerStopped)
+    }
+}
+
+// Start a thread owning a new connection, returns its request queue and cancel flag
+fn spawn(credentials: Arc<SambaCredentials>, server_root: &str, options: SambaConnectionOptions) -> Result<(Sender<SambaRequest>, Arc<AtomicBool>)> {
+    let connection = SambaConnection::connect(credentials, server_root, options)?;
+    let (requests, receiver) = mpsc::channel::<SambaRequest>();
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    let thread_cancelled = cancelled.clone();
+    thread::Builder::new()
+        .name("smb-worker".to_string())
+        .spawn(move || {
+            // Runs until the SambaWorker (and with it the sender) is dropped or cancelled.
+            // The connection is dropped together with the thread.
+            for request in receiver {
+                let cancelled = thread_cancelled.load(Ordering::Relaxed);
+
+                match request {
+                    SambaRequest::ListDirectory(_, reply) if cancelled => {
+                        let _ = reply.send(Err(SambaError::Cancelled));
+                    }
+                    SambaRequest::Stat(_, reply) if cancelled => {
+                        let _ = reply.send(Err(SambaError::Cancelled));
+                    }
+                    SambaRequest::ListDirectory(path, reply) => {
+                        let _ = reply.send(connection.list_directory(&path));
+                    }
+                    SambaRequest::Stat(path, reply) => {
+                        let _ = reply.send(connection.stat(&path));
+                    }
+                    // Also for a cancelled thread, its connection may still ask for credentials
+                    SambaRequest::AddCredentials(server, share, credentials) => {
+                        connection.add_credentials(&server, &share, credentials);
+                    }
+                }
+            }
+        })?;
+
+    Ok((requests, cancelled))
+}
@@ -0,0 +1,62 @@
+// Credentials files as written for smbclient -A and mount.cifs credentials=.
+
+use samba_printer_finder::smb::{parse_credentials_file, SambaAuthMode};
+
+#[test]
+fn long_keys() {
+    let credentials = parse_credentials_file("username = alice\npassword = secret\ndomain = CORP\n").unwrap();
+
+    assert_eq!(credentials.username, "alice");
+    assert_eq!(credentials.password.as_str(), "secret");
+    assert_eq!(credentials.workgroup, "CORP");
+    assert_eq!(credentials.auth_mode, SambaAuthMode::Password);
+}
+
+#[test]
+fn short_keys_of_mount_cifs() {
+    let credentials = parse_credentials_file("user=alice\npass=se=cret\ndom=CORP").unwrap();
+
+    assert_eq!(credentials.username, "alice");
+    // Only the first = separates key and value
+    assert_eq!(credentials.password.as_str(), "se=cret");
+    assert_eq!(credentials.workgroup, "CORP");
+}
+
+#[test]
+fn domain_in_the_username() {
+    let credentials = parse_credentials_file("username = CORP\\alice\npassword = secret").unwrap();
+    assert_eq!(credentials.username, "alice");
+    assert_eq!(credentials.workgroup, "CORP");
+
+    let credentials = parse_credentials_file("username = CORP/alice").unwrap();
+    assert_eq!(credentials.username, "alice");
+    assert_eq!(credentials.workgroup, "CORP");
+
+    // An explicit domain line wins
+    let credentials = parse_credentials_file("username = CORP\\alice\ndomain = LAB").unwrap();
+    assert_eq!(credentials.username, "alice");
+    assert_eq!(credentials.workgroup, "LAB");
+}
+
+#[test]
+fn comments_empty_lines_and_unknown_keys_are_ignored() {
+    let content = "# smbclient credentials\n\n; another comment\n  username = alice  \nuid = 1000\npassword = secret\n";
+    let credentials = parse_credentials_file(content).unwrap();
+
+    assert_eq!(credentials.username, "alice");
+    assert_eq!(credentials.password.as_str(), "secret");
+    assert_eq!(credentials.workgroup, "");
+}
+
+#[test]
+fn password_is_optional() {
+    let credentials = parse_credentials_file("username = alice").unwrap();
+    assert!(credentials.password.is_empty());
+}
+
+#[test]
+fn invalid_files_are_rejected() {
+    for content in ["", "password = secret\ndomain = CORP", "username =\npassword = secret", "username alice"] {
+        assert!(parse_credentials_file(content).is_err(), "{:?} was accepted", content);
+    }
+}
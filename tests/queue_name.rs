@@ -0,0 +1,61 @@
+// Names of CUPS queues: the rules of the scheduler, suggestions for a share and unique names.
+
+use samba_printer_finder::cups::{suggest_queue_name, unique_queue_name, validate_queue_name, InvalidQueueName, MAX_QUEUE_NAME_LEN};
+
+#[test]
+fn valid_names() {
+    for name in ["printsrv01-Floor2_Color", "HP:Floor2", "Drucker-Büro", "a.b", "..x"] {
+        assert_eq!(validate_queue_name(name), Ok(()), "{:?} was rejected", name);
+    }
+}
+
+#[test]
+fn invalid_names() {
+    assert_eq!(validate_queue_name(""), Err(InvalidQueueName::Empty));
+    assert_eq!(validate_queue_name("Floor2 Color"), Err(InvalidQueueName::Character(' ')));
+    assert_eq!(validate_queue_name("Floor2\tColor"), Err(InvalidQueueName::Character('\t')));
+    assert_eq!(validate_queue_name("printers/Floor2"), Err(InvalidQueueName::Character('/')));
+    assert_eq!(validate_queue_name("Floor2@printsrv01"), Err(InvalidQueueName::Character('@')));
+    assert_eq!(validate_queue_name("#1"), Err(InvalidQueueName::Character('#')));
+    assert_eq!(validate_queue_name("."), Err(InvalidQueueName::OnlyDots));
+    assert_eq!(validate_queue_name(".."), Err(InvalidQueueName::OnlyDots));
+
+    let long = "a".repeat(MAX_QUEUE_NAME_LEN + 1);
+    assert_eq!(validate_queue_name(&long), Err(InvalidQueueName::TooLong(MAX_QUEUE_NAME_LEN + 1)));
+    assert_eq!(validate_queue_name(&long[1..]), Ok(()));
+}
+
+#[test]
+fn suggestions_are_valid() {
+    assert_eq!(suggest_queue_name("printsrv01", "Floor2 Color"), "printsrv01-Floor2_Color");
+    assert_eq!(suggest_queue_name("printsrv01", "Büro  (2. OG)"), "printsrv01-B_ro_2._OG");
+    assert_eq!(suggest_queue_name("", "??"), "-");
+
+    let long = suggest_queue_name("printsrv01", &"x".repeat(200));
+    assert_eq!(long.len(), MAX_QUEUE_NAME_LEN);
+
+    for (server, share) in [("printsrv01", "Floor2 Color"), ("fe80::1", "Ünïcödé"), ("", "")] {
+        let name = suggest_queue_name(server, share);
+        assert_eq!(validate_queue_name(&name), Ok(()), "{:?} is no valid name", name);
+    }
+}
+
+#[test]
+fn unique_names() {
+    let existing = vec!["Floor2".to_string(), "floor2-2".to_string()];
+
+    assert_eq!(unique_queue_name("Floor3", &existing), "Floor3");
+    // CUPS compares without case
+    assert_eq!(unique_queue_name("FLOOR2", &existing), "FLOOR2-3");
+}
+
+#[test]
+fn unique_names_stay_within_the_length_limit() {
+    let name = "a".repeat(MAX_QUEUE_NAME_LEN);
+    let existing = vec![name.clone()];
+
+    let unique = unique_queue_name(&name, &existing);
+    assert_eq!(unique.len(), MAX_QUEUE_NAME_LEN);
+    assert!(unique.ends_with("-2"));
+    assert_eq!(validate_queue_name(&unique), Ok(()));
+}
@@ -0,0 +1,188 @@
+// Runs SecretStore against a small in-memory Secret Service on a private session bus.
+// Needs dbus-daemon, which gio::TestDBus starts for the duration of the test.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use glib::prelude::*;
+use glib::variant::ObjectPath;
+use glib::Variant;
+use gtk::gio::{self, DBusConnection, DBusConnectionFlags, DBusNodeInfo, TestDBus, TestDBusFlags};
+use samba_printer_finder::secrets::SecretStore;
+use samba_printer_finder::smb::SambaPassword;
+
+const MOCK_XML: &str = r#"
+<node>
+  <interface name="org.freedesktop.Secret.Service">
+    <method name="OpenSession">
+      <arg type="s" direction="in"/><arg type="v" direction="in"/>
+      <arg type="v" direction="out"/><arg type="o" direction="out"/>
+    </method>
+    <method name="SearchItems">
+      <arg type="a{ss}" direction="in"/>
+      <arg type="ao" direction="out"/><arg type="ao" direction="out"/>
+    </method>
+    <method name="Unlock">
+      <arg type="ao" direction="in"/>
+      <arg type="ao" direction="out"/><arg type="o" direction="out"/>
+    </method>
+    <method name="GetSecrets">
+      <arg type="ao" direction="in"/><arg type="o" direction="in"/>
+      <arg type="a{o(oayays)}" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.Secret.Collection">
+    <method name="CreateItem">
+      <arg type="a{sv}" direction="in"/><arg type="(oayays)" direction="in"/><arg type="b" direction="in"/>
+      <arg type="o" direction="out"/><arg type="o" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.Secret.Item">
+    <method name="Delete">
+      <arg type="o" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+const SESSION_PATH: &str = "/org/freedesktop/secrets/session/1";
+
+// Item path -> (attributes, secret)
+type Items = Rc<RefCell<HashMap<String, (HashMap<String, String>, Vec<u8>)>>>;
+
+fn object_path(path: &str) -> ObjectPath {
+    ObjectPath::try_from(path).unwrap()
+}
+
+fn no_prompt() -> ObjectPath {
+    object_path("/")
+}
+
+// Register the mock service on `connection`, every object is unlocked and needs no prompt
+fn register_mock(connection: &DBusConnection, items: Items) {
+    let node = DBusNodeInfo::for_xml(MOCK_XML).unwrap();
+    let service = node.lookup_interface("org.freedesktop.Secret.Service").unwrap();
+    let collection = node.lookup_interface("org.freedesktop.Secret.Collection").unwrap();
+
+    let service_items = items.clone();
+    connection.register_object("/org/freedesktop/secrets", &service)
+        .method_call(move |_, _, _, _, method, parameters, invocation| {
+            let reply = match method {
+                "OpenSession" => ("".to_variant(), object_path(SESSION_PATH)).to_variant(),
+                "SearchItems" => {
+                    let (wanted,) = parameters.get::<(HashMap<String, String>,)>().unwrap();
+                    let found: Vec<ObjectPath> = service_items.borrow().iter()
+                        .filter(|(_, (attributes, _))| wanted.iter().all(|(k, v)| attributes.get(k) == Some(v)))
+                        .map(|(path, _)| object_path(path))
+                        .collect();
+                    (found, Vec::<ObjectPath>::new()).to_variant()
+                }
+                "Unlock" => {
+                    let (objects,) = parameters.get::<(Vec<ObjectPath>,)>().unwrap();
+                    (objects, no_prompt()).to_variant()
+                }
+                "GetSecrets" => {
+                    let (wanted, session) = parameters.get::<(Vec<ObjectPath>, ObjectPath)>().unwrap();
+                    let secrets: HashMap<ObjectPath, (ObjectPath, Vec<u8>, Vec<u8>, String)> = wanted.into_iter()
+                        .filter_map(|path| {
+                            let secret = service_items.borrow().get(path.as_str()).map(|(_, secret)| secret.clone())?;
+                            Some((path, (session.clone(), Vec::new(), secret, "text/plain".to_string())))
+                        })
+                        .collect();
+                    (secrets,).to_variant()
+                }
+                _ => unreachable!(),
+            };
+            invocation.return_value(Some(&reply));
+        })
+        .build()
+        .unwrap();
+
+    let node = Rc::new(node);
+    let next_id = Rc::new(RefCell::new(0));
+    connection.register_object("/org/freedesktop/secrets/aliases/default", &collection)
+        .method_call(move |connection, _, _, _, _, parameters, invocation| {
+            let properties = parameters.child_value(0).get::<HashMap<String, Variant>>().unwrap();
+            let attributes = properties["org.freedesktop.Secret.Item.Attributes"].get::<HashMap<String, String>>().unwrap();
+            let (_, _, secret, _) = parameters.child_value(1).get::<(ObjectPath, Vec<u8>, Vec<u8>, String)>().unwrap();
+            let replace = parameters.child_value(2).get::<bool>().unwrap();
+
+            let existing = items.borrow().iter()
+                .find(|(_, (a, _))| *a == attributes)
+                .map(|(path, _)| path.clone());
+
+            let path = match existing {
+                Some(path) if replace => path,
+                _ => {
+                    *next_id.borrow_mut() += 1;
+                    let path = format!("/org/freedesktop/secrets/collection/login/{}", next_id.borrow());
+                    register_item(&connection, &node, &path, items.clone());
+                    path
+                }
+            };
+
+            items.borrow_mut().insert(path.clone(), (attributes, secret));
+            invocation.return_value(Some(&(object_path(&path), no_prompt()).to_variant()));
+        })
+        .build()
+        .unwrap();
+}
+
+fn register_item(connection: &DBusConnection, node: &DBusNodeInfo, path: &str, items: Items) {
+    let item = node.lookup_interface("org.freedesktop.Secret.Item").unwrap();
+    let item_path = path.to_string();
+
+    connection.register_object(path, &item)
+        .method_call(move |_, _, _, _, _, _, invocation| {
+            items.borrow_mut().remove(&item_path);
+            invocation.return_value(Some(&(no_prompt(),).to_variant()));
+        })
+        .build()
+        .unwrap();
+}
+
+fn connect(address: &str) -> DBusConnection {
+    DBusConnection::for_address_sync(
+        address,
+        DBusConnectionFlags::AUTHENTICATION_CLIENT | DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+        None,
+        None::<&gio::Cancellable>,
+    ).unwrap()
+}
+
+#[test]
+fn store_lookup_and_delete_passwords() {
+    let bus = TestDBus::new(TestDBusFlags::NONE);
+    bus.up();
+    let address = bus.bus_address().unwrap();
+
+    let items: Items = Rc::new(RefCell::new(HashMap::new()));
+    let service_connection = connect(&address);
+    register_mock(&service_connection, items.clone());
+    let service_name = service_connection.unique_name().unwrap();
+
+    glib::MainContext::default().block_on(async {
+        let store = SecretStore::with_connection(connect(&address), &service_name).await.unwrap();
+
+        assert!(store.lookup("printsrv01", "CORP").await.unwrap().is_none());
+
+        store.store("printsrv01", "CORP", "alice", "s3cr:et;@pw").await.unwrap();
+        assert_eq!(store.lookup("PrintSrv01", "corp").await.unwrap().as_ref().map(SambaPassword::as_str), Some("s3cr:et;@pw"));
+
+        // Another domain on the same server is a separate password
+        assert!(store.lookup("printsrv01", "").await.unwrap().is_none());
+
+        // Storing again replaces the password instead of adding a second one
+        store.store("printsrv01", "CORP", "alice", "new password").await.unwrap();
+        assert_eq!(items.borrow().len(), 1);
+        assert_eq!(store.lookup("printsrv01", "CORP").await.unwrap().as_ref().map(SambaPassword::as_str), Some("new password"));
+
+        store.delete("printsrv01", "CORP").await.unwrap();
+        assert!(store.lookup("printsrv01", "CORP").await.unwrap().is_none());
+    });
+
+    drop(service_connection);
+    bus.down();
+}